@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use log::info;
+use serde_yml::{Mapping, Value};
+
+use crate::combat::{
+    entity::Entity,
+    open5e::{self, Open5eMonster},
+};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        /// Missing for the combat file itself
+        path: Option<PathBuf>,
+        source: serde_yml::Error,
+    },
+    /// A stat block in the bestiary without a `name`
+    MissingName(PathBuf),
+    UnknownMonster {
+        name: String,
+        known: Vec<String>,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, source } => {
+                write!(f, "failed to read '{}': {source}", path.display())
+            }
+            LoadError::Parse {
+                path: Some(path),
+                source,
+            } => write!(f, "failed to parse '{}': {source}", path.display()),
+            LoadError::Parse { path: None, source } => {
+                write!(f, "failed to parse the combat: {source}")
+            }
+            LoadError::MissingName(path) => {
+                write!(f, "a stat block in '{}' has no name", path.display())
+            }
+            LoadError::UnknownMonster { name, known } if known.is_empty() => {
+                write!(f, "unknown monster '{name}', the bestiary is empty")
+            }
+            LoadError::UnknownMonster { name, known } => write!(
+                f,
+                "unknown monster '{name}', the bestiary has: {}",
+                known.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A library of monster stat blocks that combat files reference with `monster: goblin`
+/// instead of repeating the `stats`
+#[derive(Debug, Clone, Default)]
+pub struct Bestiary {
+    /// Stat blocks by their normalized name
+    stat_blocks: HashMap<String, Value>,
+}
+
+impl Bestiary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `.yaml`, `.yml` and `.json` file in `dir`.
+    /// A file holds a single stat block or a list of them.
+    pub fn load_dir(dir: &Path) -> Result<Self, LoadError> {
+        let io_error = |source| LoadError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut bestiary = Self::new();
+        let files = fs::read_dir(dir)
+            .map_err(io_error)?
+            .map_ok(|entry| entry.path())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error)?;
+        for path in files.into_iter().sorted() {
+            let is_stat_block = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    ["yaml", "yml", "json"]
+                        .iter()
+                        .any(|known| ext.eq_ignore_ascii_case(known))
+                });
+            if path.is_file() && is_stat_block {
+                bestiary.load_file(&path)?;
+            }
+        }
+        info!(
+            "Loaded {} stat blocks from {}",
+            bestiary.len(),
+            dir.display()
+        );
+        Ok(bestiary)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<(), LoadError> {
+        let content = fs::read_to_string(path).map_err(|source| LoadError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        // YAML is a superset of JSON, so both are parsed the same way
+        let value: Value = serde_yml::from_str(&content).map_err(|source| LoadError::Parse {
+            path: Some(path.to_path_buf()),
+            source,
+        })?;
+        let stat_blocks = match value {
+            Value::Sequence(stat_blocks) => stat_blocks,
+            // a page of the Open5e API
+            Value::Mapping(mut page) if page.get("results").is_some_and(Value::is_sequence) => {
+                match page.remove("results") {
+                    Some(Value::Sequence(stat_blocks)) => stat_blocks,
+                    _ => Vec::new(),
+                }
+            }
+            stat_block => vec![stat_block],
+        };
+        for stat_block in stat_blocks {
+            let stat_block = if open5e::is_open5e(&stat_block) {
+                from_open5e(stat_block).map_err(|source| LoadError::Parse {
+                    path: Some(path.to_path_buf()),
+                    source,
+                })?
+            } else {
+                stat_block
+            };
+            if !self.insert(stat_block) {
+                return Err(LoadError::MissingName(path.to_path_buf()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a stat block, replacing one with the same name.
+    /// Returns false if it doesn't have a name.
+    pub fn insert(&mut self, stat_block: Value) -> bool {
+        let Some(name) = stat_block.get("name").and_then(|n| n.as_str()) else {
+            return false;
+        };
+        self.stat_blocks.insert(normalize(name), stat_block);
+        true
+    }
+
+    /// Looks up a stat block, ignoring case and treating `-` and `_` like spaces
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.stat_blocks.get(&normalize(name))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.stat_blocks.keys().sorted().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stat_blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stat_blocks.is_empty()
+    }
+
+    /// Replaces the `monster: <name>` references of the `monsters` in a combat
+    /// with the stat block from the bestiary. The entry's `stats` override single fields.
+    pub fn resolve(&self, combat: &mut Value) -> Result<(), LoadError> {
+        let Some(monsters) = combat.get_mut("monsters").and_then(|m| m.as_sequence_mut()) else {
+            return Ok(());
+        };
+        for entry in monsters.iter_mut() {
+            let Some(entry) = entry.as_mapping_mut() else {
+                continue;
+            };
+            let Some(reference) = entry.remove("monster") else {
+                continue;
+            };
+            let name = match &reference {
+                Value::String(name) => name.clone(),
+                other => serde_yml::to_string(other)
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            };
+            let mut stats = self
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| LoadError::UnknownMonster {
+                    name: name.clone(),
+                    known: self.names(),
+                })?;
+            if let (Some(stats), Some(Value::Mapping(overrides))) =
+                (stats.as_mapping_mut(), entry.remove("stats"))
+            {
+                merge(stats, overrides);
+            }
+            entry.insert(Value::from("stats"), stats);
+        }
+        Ok(())
+    }
+}
+
+/// Fields of a serialized [`Entity`] that belong to a monster in a fight, not to its stat block
+const RUNTIME_FIELDS: [&str; 14] = [
+    "uuid",
+    "id",
+    "initiative",
+    "current_hp",
+    "temp_hp",
+    "conditions",
+    "condition_durations",
+    "effects",
+    "concentration",
+    "death_saves",
+    "delaying",
+    "initiative_order",
+    "readied",
+    "legendary_actions_used",
+];
+
+/// Converts an Open5e or 5e-SRD stat block into the format of the combat files
+fn from_open5e(stat_block: Value) -> Result<Value, serde_yml::Error> {
+    let entity: Entity = serde_yml::from_value::<Open5eMonster>(stat_block)?.into();
+    let mut stat_block = serde_yml::to_value(&entity)?;
+    // every monster created from the stat block gets its own, e.g. full hit points
+    // after overriding `max_hp`
+    if let Some(stat_block) = stat_block.as_mapping_mut() {
+        for field in RUNTIME_FIELDS {
+            stat_block.remove(field);
+        }
+    }
+    Ok(stat_block)
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace(['-', '_'], " ")
+}
+
+fn merge(stats: &mut Mapping, overrides: Mapping) {
+    for (key, value) in overrides {
+        stats.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goblin() -> Value {
+        serde_yml::from_str(
+            "
+name: Goblin
+initiative_modifier: 2
+ac: 15
+max_hp: 7
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut bestiary = Bestiary::new();
+        assert!(bestiary.insert(goblin()));
+        assert!(!bestiary.insert(Value::from("no name")));
+        let mut combat: Value = serde_yml::from_str(
+            "
+monsters:
+  - count: 2
+    monster: goblin
+  - monster: GOBLIN
+    stats:
+      name: Goblin Boss
+      max_hp: 21
+  - stats:
+      name: Orc
+      initiative_modifier: 1
+      ac: 13
+      max_hp: 15
+",
+        )
+        .unwrap();
+        bestiary.resolve(&mut combat).unwrap();
+        let monsters = combat["monsters"].as_sequence().unwrap();
+        assert_eq!(monsters[0]["stats"], goblin());
+        assert!(monsters[0].get("monster").is_none());
+        assert_eq!(monsters[1]["stats"]["name"], Value::from("Goblin Boss"));
+        assert_eq!(monsters[1]["stats"]["max_hp"], Value::from(21));
+        assert_eq!(monsters[1]["stats"]["ac"], Value::from(15));
+        assert_eq!(monsters[2]["stats"]["name"], Value::from("Orc"));
+
+        let mut combat: Value = serde_yml::from_str("monsters: [{monster: gobln}]").unwrap();
+        let error = bestiary.resolve(&mut combat).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown monster 'gobln', the bestiary has: goblin"
+        );
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("rpg-combat-bestiary-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("goblins.yaml"),
+            "- name: Goblin\n  ac: 15\n- name: Goblin Boss\n  ac: 17\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("young_red_dragon.json"),
+            r#"{"name": "Young Red Dragon", "ac": 18}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "not a stat block").unwrap();
+
+        let bestiary = Bestiary::load_dir(&dir).unwrap();
+        assert_eq!(
+            bestiary.names(),
+            vec!["goblin", "goblin boss", "young red dragon"]
+        );
+        assert!(bestiary.get("young-red-dragon").is_some());
+
+        fs::write(dir.join("broken.yml"), "- ac: 12\n").unwrap();
+        assert!(matches!(
+            Bestiary::load_dir(&dir),
+            Err(LoadError::MissingName(_))
+        ));
+        assert!(matches!(
+            Bestiary::load_dir(&dir.join("missing")),
+            Err(LoadError::Io { .. })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_open5e_fixtures() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let mut bestiary = Bestiary::load_dir(&fixtures.join("open5e")).unwrap();
+        bestiary
+            .load_file(&fixtures.join("5e-srd/monsters.json"))
+            .unwrap();
+        assert_eq!(
+            bestiary.names(),
+            vec!["adult red dragon", "goblin", "mage", "young black dragon"]
+        );
+        let goblin = bestiary.get("goblin").unwrap();
+        assert_eq!(goblin["max_hp"], Value::from(7));
+        assert_eq!(goblin["initiative_modifier"], Value::from(2));
+        assert!(goblin.get("uuid").is_none());
+        assert!(goblin.get("current_hp").is_none());
+        assert!(goblin.get("conditions").is_none());
+
+        let mut combat: Value = serde_yml::from_str(
+            "
+players: []
+monsters:
+  - count: 2
+    monster: adult-red-dragon
+  - monster: goblin
+    stats:
+      max_hp: 20
+",
+        )
+        .unwrap();
+        bestiary.resolve(&mut combat).unwrap();
+        let ct = crate::combat::tracker::CombatTracker::from_yaml(
+            serde_yml::to_string(&combat).unwrap(),
+        );
+        let dragons: Vec<_> = ct
+            .entities
+            .iter()
+            .filter(|e| e.name == "Adult Red Dragon")
+            .collect();
+        assert_eq!(dragons.len(), 2);
+        assert_ne!(dragons[0].uuid, dragons[1].uuid);
+        assert_eq!(dragons[0].legendary_actions, 3);
+        assert_eq!(dragons[0].current_hp, 256);
+        let goblin = ct.entities.iter().find(|e| e.name == "Goblin").unwrap();
+        assert_eq!((goblin.current_hp, goblin.max_hp), (20, 20));
+    }
+}
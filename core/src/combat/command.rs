@@ -0,0 +1,134 @@
+use uuid::Uuid;
+
+use crate::combat::{
+    damage::DamageType,
+    entity::{Condition, ConditionDuration, Effect, Entity, Trigger},
+};
+
+/// A single mutation of the [`CombatTracker`](crate::combat::tracker::CombatTracker).
+///
+/// All changes made from the UI go through [`CombatTracker::execute`](crate::combat::tracker::CombatTracker::execute)
+/// so they can be undone and redone.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `critical` marks the damage of a critical hit, which counts as two failed death saves
+    Damage {
+        target: Uuid,
+        amount: i32,
+        damage_type: Option<DamageType>,
+        critical: bool,
+    },
+    Heal {
+        target: Uuid,
+        amount: i32,
+    },
+    SetTempHp {
+        target: Uuid,
+        amount: i32,
+    },
+    SetInitiative {
+        target: Uuid,
+        initiative: i32,
+    },
+    /// Removes the condition if present, otherwise adds it with the optional duration
+    ToggleCondition {
+        target: Uuid,
+        condition: Condition,
+        duration: Option<ConditionDuration>,
+    },
+    /// Adds the effect or replaces the one with the same name
+    AddEffect {
+        target: Uuid,
+        effect: Effect,
+    },
+    RemoveEffect {
+        target: Uuid,
+        name: String,
+    },
+    StartConcentration {
+        target: Uuid,
+        spell: String,
+    },
+    /// Ends the concentration and removes the effects the target applied with that spell
+    EndConcentration {
+        target: Uuid,
+    },
+    /// A death save of a dying player, `roll` is the natural d20 roll
+    DeathSave {
+        target: Uuid,
+        roll: i32,
+    },
+    Stabilize {
+        target: Uuid,
+    },
+    /// Takes the target out at 0 hit points without any damage roll, a player is dead right away
+    Kill {
+        target: Uuid,
+    },
+    /// Takes the current entity out of the rotation and passes the turn on
+    DelayTurn,
+    /// Puts a delaying entity back into the initiative order right after `after`
+    ResumeTurn {
+        target: Uuid,
+        after: Uuid,
+    },
+    /// Readies an action, described with its trigger
+    Ready {
+        target: Uuid,
+        action: String,
+    },
+    /// The trigger of the readied action happened and the entity used it
+    UseReadied {
+        target: Uuid,
+    },
+    /// Spends legendary action points of the target
+    UseLegendaryAction {
+        target: Uuid,
+        cost: u32,
+    },
+    /// Uses a recharge or per-day ability of the target by name
+    UseAbility {
+        target: Uuid,
+        name: String,
+    },
+    /// Makes a used ability available again, e.g. after a long rest
+    RestoreAbility {
+        target: Uuid,
+        name: String,
+    },
+    AddTrigger {
+        target: Uuid,
+        trigger: Trigger,
+    },
+    /// Removes the target's own trigger at `index`, triggers of effects go with the effect
+    RemoveTrigger {
+        target: Uuid,
+        index: usize,
+    },
+    RollInitiative {
+        group_by_name: bool,
+        re_roll: bool,
+    },
+    /// Adds entities mid-combat, missing initiatives are rolled
+    AddEntities(Vec<Entity>),
+    RemoveEntity {
+        target: Uuid,
+    },
+    NextTurn,
+    PrevTurn,
+    ResetCombat,
+    /// Several commands that are applied and undone together, e.g. the damage of a Fireball
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Combines `commands` into one, `None` if there are none.
+    /// A single command isn't wrapped, so it's described as before.
+    pub fn batch(mut commands: Vec<Command>) -> Option<Command> {
+        match commands.len() {
+            0 => None,
+            1 => commands.pop(),
+            _ => Some(Command::Batch(commands)),
+        }
+    }
+}
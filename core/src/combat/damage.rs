@@ -0,0 +1,121 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum DamageType {
+    #[serde(alias = "acid")]
+    Acid,
+    #[serde(alias = "bludgeoning")]
+    Bludgeoning,
+    #[serde(alias = "cold")]
+    Cold,
+    #[serde(alias = "fire")]
+    Fire,
+    #[serde(alias = "force")]
+    Force,
+    #[serde(alias = "lightning")]
+    Lightning,
+    #[serde(alias = "necrotic")]
+    Necrotic,
+    #[serde(alias = "piercing")]
+    Piercing,
+    #[serde(alias = "poison")]
+    Poison,
+    #[serde(alias = "psychic")]
+    Psychic,
+    #[serde(alias = "radiant")]
+    Radiant,
+    #[serde(alias = "slashing")]
+    Slashing,
+    #[serde(alias = "thunder")]
+    Thunder,
+}
+
+impl DamageType {
+    pub const ALL: [DamageType; 13] = [
+        DamageType::Acid,
+        DamageType::Bludgeoning,
+        DamageType::Cold,
+        DamageType::Fire,
+        DamageType::Force,
+        DamageType::Lightning,
+        DamageType::Necrotic,
+        DamageType::Piercing,
+        DamageType::Poison,
+        DamageType::Psychic,
+        DamageType::Radiant,
+        DamageType::Slashing,
+        DamageType::Thunder,
+    ];
+}
+
+impl fmt::Display for DamageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for DamageType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DamageType::ALL
+            .into_iter()
+            .find(|t| t.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown damage type '{s}'"))
+    }
+}
+
+/// Why the damage an entity took differs from the rolled amount
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DamageModifier {
+    Immune,
+    Resistant,
+    Vulnerable,
+}
+
+impl fmt::Display for DamageModifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DamageModifier::Immune => write!(f, "immune"),
+            DamageModifier::Resistant => write!(f, "resistant, halved"),
+            DamageModifier::Vulnerable => write!(f, "vulnerable, doubled"),
+        }
+    }
+}
+
+/// Splits a trailing damage type off an input like `2d6+3 fire`.
+/// Returns the input unchanged if the last word isn't a damage type.
+pub fn split_damage_type(input: &str) -> (&str, Option<DamageType>) {
+    let input = input.trim();
+    if let Some((amount, last)) = input.rsplit_once(char::is_whitespace) {
+        if let Ok(damage_type) = last.parse::<DamageType>() {
+            return (amount.trim_end(), Some(damage_type));
+        }
+    }
+    (input, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_damage_type() {
+        assert_eq!(split_damage_type("14 fire"), ("14", Some(DamageType::Fire)));
+        assert_eq!(
+            split_damage_type(" 2d6 + 3  Slashing "),
+            ("2d6 + 3", Some(DamageType::Slashing))
+        );
+        assert_eq!(split_damage_type("14"), ("14", None));
+        assert_eq!(split_damage_type("1d6 + 2"), ("1d6 + 2", None));
+        assert_eq!(split_damage_type("14 banana"), ("14 banana", None));
+    }
+
+    #[test]
+    fn test_yaml_accepts_lowercase() {
+        let types: Vec<DamageType> = serde_yml::from_str("[fire, Cold]").unwrap();
+        assert_eq!(types, vec![DamageType::Fire, DamageType::Cold]);
+    }
+}
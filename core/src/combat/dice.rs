@@ -1,5 +1,774 @@
+use std::{fmt, str::FromStr};
+
+use itertools::Itertools;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound for the number of dice in a single term, guards against typos like `2000d6`.
+const MAX_DICE: u32 = 1000;
+/// Upper bound for the sides of a die.
+const MAX_SIDES: u32 = 1000;
+/// Upper bound for additional dice produced by exploding a single term.
+const MAX_EXPLOSIONS: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiceError {
+    Empty,
+    UnexpectedChar {
+        found: char,
+        position: usize,
+    },
+    ExpectedNumber(usize),
+    NumberTooLarge(usize),
+    NoSides,
+    TooManySides,
+    TooManyDice,
+    InvalidKeep {
+        keep: u32,
+        count: u32,
+    },
+    MultipleKeep(usize),
+    /// `adv` / `dis` on a term with more than one die
+    InvalidAdvantage(usize),
+    InvalidExplode(u32),
+    Overflow,
+}
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiceError::Empty => write!(f, "empty dice expression"),
+            DiceError::UnexpectedChar { found, position } => {
+                write!(f, "unexpected '{found}' at position {position}")
+            }
+            DiceError::ExpectedNumber(position) => {
+                write!(f, "expected a number at position {position}")
+            }
+            DiceError::NumberTooLarge(position) => {
+                write!(f, "number too large at position {position}")
+            }
+            DiceError::NoSides => write!(f, "dice need at least one side"),
+            DiceError::TooManySides => write!(f, "dice can have at most {MAX_SIDES} sides"),
+            DiceError::TooManyDice => write!(f, "at most {MAX_DICE} dice can be rolled at once"),
+            DiceError::InvalidKeep { keep, count } => {
+                write!(f, "cannot keep or drop {keep} of {count} dice")
+            }
+            DiceError::MultipleKeep(position) => {
+                write!(
+                    f,
+                    "only one keep or drop modifier allowed at position {position}"
+                )
+            }
+            DiceError::InvalidAdvantage(position) => write!(
+                f,
+                "advantage and disadvantage need a single die at position {position}"
+            ),
+            DiceError::InvalidExplode(sides) => write!(f, "a d{sides} cannot explode"),
+            DiceError::Overflow => write!(f, "the result is too large"),
+        }
+    }
+}
+
+impl std::error::Error for DiceError {}
+
+/// Which dice of a term count towards its total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Keep {
+    Highest(u32),
+    Lowest(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiceTerm {
+    pub count: u32,
+    pub sides: u32,
+    pub keep: Option<Keep>,
+    pub explode: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TermKind {
+    Constant(i32),
+    Dice(DiceTerm),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Term {
+    pub negative: bool,
+    pub kind: TermKind,
+}
+
+/// A parsed dice expression like `2d6+3`, `4d6kh3`, `1d20adv`, `8d6!` or `1d8+1d6+2`.
+///
+/// Supported notation per dice term (`NdS`, `N` defaults to 1, `d%` is a d100):
+/// - `khN` / `klN` keep the highest / lowest N dice (`kN` is short for `khN`)
+/// - `dhN` / `dlN` drop the highest / lowest N dice
+/// - `adv` / `dis` roll a single die twice and keep the better / worse one
+/// - `!` explodes: every die showing its maximum adds another die
+///
+/// A term takes at most one keep, drop, `adv` or `dis` modifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiceExpr {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DieRoll {
+    pub value: i32,
+    pub kept: bool,
+    pub exploded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TermResult {
+    pub term: Term,
+    pub dice: Vec<DieRoll>,
+    /// Signed contribution of this term to the total
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollResult {
+    pub terms: Vec<TermResult>,
+    pub total: i32,
+}
 
+/// Rolls a single die as a `1dS` [`DiceExpr`] and adds `modifier`, e.g. a d20 for initiative.
 pub fn roll_dice<R: Rng>(rng: &mut R, sides: i32, modifier: i32) -> i32 {
-    rng.random_range(1..=sides) + modifier
+    let die = DiceExpr {
+        terms: vec![Term {
+            negative: false,
+            kind: TermKind::Dice(DiceTerm {
+                count: 1,
+                sides: sides.clamp(1, MAX_SIDES as i32) as u32,
+                keep: None,
+                explode: false,
+            }),
+        }],
+    };
+    die.roll(rng)
+        .expect("a single die can't overflow")
+        .total
+        .saturating_add(modifier)
+}
+
+/// Parses and rolls `expression` in one go.
+pub fn roll_expression<R: Rng>(rng: &mut R, expression: &str) -> Result<RollResult, DiceError> {
+    expression.parse::<DiceExpr>()?.roll(rng)
+}
+
+impl DiceExpr {
+    /// Rolls every term. Fails with [`DiceError::Overflow`] if a term or the total
+    /// doesn't fit into an `i32`.
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> Result<RollResult, DiceError> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|term| term.roll(rng))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total = checked_sum(terms.iter().map(|t| t.value))?;
+        Ok(RollResult { terms, total })
+    }
+
+    /// The damage of a critical hit: every dice term rolls twice as many dice, modifiers stay.
+    /// `1d8+3` becomes `2d8+3`. Fails with [`DiceError::TooManyDice`] if a term would roll
+    /// more dice than the parser allows.
+    pub fn critical(&self) -> Result<DiceExpr, DiceError> {
+        let terms = self
+            .terms
+            .iter()
+            .map(|term| match &term.kind {
+                TermKind::Dice(dice) => Ok(Term {
+                    negative: term.negative,
+                    kind: TermKind::Dice(DiceTerm {
+                        count: doubled(dice.count)?,
+                        keep: dice.keep.map(|keep| match keep {
+                            Keep::Highest(n) => Keep::Highest(n * 2),
+                            Keep::Lowest(n) => Keep::Lowest(n * 2),
+                        }),
+                        ..dice.clone()
+                    }),
+                }),
+                TermKind::Constant(_) => Ok(term.clone()),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(DiceExpr { terms })
+    }
+
+    /// Returns true if the expression doesn't contain any dice.
+    pub fn is_constant(&self) -> bool {
+        self.terms
+            .iter()
+            .all(|t| matches!(t.kind, TermKind::Constant(_)))
+    }
+}
+
+impl Term {
+    fn roll<R: Rng>(&self, rng: &mut R) -> Result<TermResult, DiceError> {
+        let (dice, value) = match &self.kind {
+            TermKind::Constant(value) => (Vec::new(), *value),
+            TermKind::Dice(dice_term) => {
+                let dice = dice_term.roll(rng);
+                let value = checked_sum(dice.iter().filter(|d| d.kept).map(|d| d.value))?;
+                (dice, value)
+            }
+        };
+        let value = if self.negative {
+            value.checked_neg().ok_or(DiceError::Overflow)?
+        } else {
+            value
+        };
+        Ok(TermResult {
+            term: self.clone(),
+            dice,
+            value,
+        })
+    }
+}
+
+fn checked_sum(mut values: impl Iterator<Item = i32>) -> Result<i32, DiceError> {
+    values.try_fold(0i32, |sum, value| {
+        sum.checked_add(value).ok_or(DiceError::Overflow)
+    })
+}
+
+/// Doubles the dice of a critical hit, checked before doubling so the result stays
+/// within [`MAX_DICE`].
+fn doubled(count: u32) -> Result<u32, DiceError> {
+    if count > MAX_DICE / 2 {
+        return Err(DiceError::TooManyDice);
+    }
+    Ok(count * 2)
+}
+
+impl DiceTerm {
+    fn roll<R: Rng>(&self, rng: &mut R) -> Vec<DieRoll> {
+        let sides = self.sides as i32;
+        let mut dice = Vec::with_capacity(self.count as usize);
+        let mut explosions = 0;
+        for _ in 0..self.count {
+            let mut value = rng.random_range(1..=sides);
+            while self.explode && value == sides && explosions < MAX_EXPLOSIONS {
+                dice.push(DieRoll {
+                    value,
+                    kept: true,
+                    exploded: true,
+                });
+                explosions += 1;
+                value = rng.random_range(1..=sides);
+            }
+            dice.push(DieRoll {
+                value,
+                kept: true,
+                exploded: false,
+            });
+        }
+
+        if let Some(keep) = self.keep {
+            let (n, highest) = match keep {
+                Keep::Highest(n) => (n as usize, true),
+                Keep::Lowest(n) => (n as usize, false),
+            };
+            let order: Vec<usize> = (0..dice.len())
+                .sorted_by(|&a, &b| {
+                    let cmp = dice[a].value.cmp(&dice[b].value);
+                    if highest {
+                        cmp.reverse()
+                    } else {
+                        cmp
+                    }
+                })
+                .collect();
+            for &i in order.iter().skip(n) {
+                dice[i].kept = false;
+            }
+        }
+        dice
+    }
+}
+
+impl FromStr for DiceExpr {
+    type Err = DiceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).parse()
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    /// Position of each of `chars` in the input, which still contains the whitespace
+    positions: Vec<usize>,
+    /// Length of the input, the position reported at its end
+    len: usize,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        let (positions, chars) = input
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .map(|(i, c)| (i, c.to_ascii_lowercase()))
+            .unzip();
+        Self {
+            chars,
+            positions,
+            len: input.chars().count(),
+            pos: 0,
+        }
+    }
+
+    /// Position in the input of the character at `pos`, for error messages
+    fn position(&self, pos: usize) -> usize {
+        self.positions.get(pos).copied().unwrap_or(self.len)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        let end = self.pos + expected.len();
+        if end <= self.chars.len()
+            && self.chars[self.pos..end]
+                .iter()
+                .copied()
+                .eq(expected.chars())
+        {
+            self.pos = end;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unexpected(&self) -> DiceError {
+        match self.peek() {
+            Some(found) => DiceError::UnexpectedChar {
+                found,
+                position: self.position(self.pos),
+            },
+            None => DiceError::ExpectedNumber(self.position(self.pos)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Option<u32>, DiceError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse::<u32>()
+            .map(Some)
+            .map_err(|_| DiceError::NumberTooLarge(self.position(start)))
+    }
+
+    fn parse(mut self) -> Result<DiceExpr, DiceError> {
+        if self.chars.is_empty() {
+            return Err(DiceError::Empty);
+        }
+        let mut terms = Vec::new();
+        let mut negative = self.eat("-");
+        if !negative {
+            self.eat("+");
+        }
+        loop {
+            terms.push(Term {
+                negative,
+                kind: self.term()?,
+            });
+            if self.eat("+") {
+                negative = false;
+            } else if self.eat("-") {
+                negative = true;
+            } else if self.peek().is_none() {
+                break;
+            } else {
+                return Err(self.unexpected());
+            }
+        }
+        Ok(DiceExpr { terms })
+    }
+
+    fn term(&mut self) -> Result<TermKind, DiceError> {
+        let count_pos = self.pos;
+        let count = self.number()?;
+        if !self.eat("d") {
+            return match count {
+                Some(value) => i32::try_from(value)
+                    .map(TermKind::Constant)
+                    .map_err(|_| DiceError::NumberTooLarge(self.position(count_pos))),
+                None => Err(self.unexpected()),
+            };
+        }
+        let mut count = count.unwrap_or(1);
+        if count > MAX_DICE {
+            return Err(DiceError::TooManyDice);
+        }
+        let sides = if self.eat("%") {
+            100
+        } else {
+            self.number()?
+                .ok_or(DiceError::ExpectedNumber(self.position(self.pos)))?
+        };
+        if sides == 0 {
+            return Err(DiceError::NoSides);
+        }
+        if sides > MAX_SIDES {
+            return Err(DiceError::TooManySides);
+        }
+
+        let mut keep = None;
+        let mut explode = false;
+        // adv/dis change the count, a second one must still be reported as a second modifier
+        let dice = count;
+        loop {
+            if self.eat("!") {
+                if sides < 2 {
+                    return Err(DiceError::InvalidExplode(sides));
+                }
+                explode = true;
+                continue;
+            }
+            let position = self.pos;
+            let modifier = if self.eat("adv") {
+                count = self.advantage(dice, position)?;
+                Keep::Highest(1)
+            } else if self.eat("dis") {
+                count = self.advantage(dice, position)?;
+                Keep::Lowest(1)
+            } else if self.eat("kl") {
+                Keep::Lowest(self.keep_amount(count)?)
+            } else if self.eat("kh") || self.eat("k") {
+                Keep::Highest(self.keep_amount(count)?)
+            } else if self.eat("dh") {
+                Keep::Lowest(count - self.keep_amount(count)?)
+            } else if self.eat("dl") {
+                Keep::Highest(count - self.keep_amount(count)?)
+            } else {
+                break;
+            };
+            if keep.replace(modifier).is_some() {
+                return Err(DiceError::MultipleKeep(self.position(position)));
+            }
+        }
+        Ok(TermKind::Dice(DiceTerm {
+            count,
+            sides,
+            keep,
+            explode,
+        }))
+    }
+
+    /// The dice of an `adv`/`dis` term. Keeping the best dice of several isn't the better
+    /// of two totals, so only a single die can be rolled with advantage.
+    fn advantage(&self, count: u32, position: usize) -> Result<u32, DiceError> {
+        if count != 1 {
+            return Err(DiceError::InvalidAdvantage(self.position(position)));
+        }
+        Ok(2)
+    }
+
+    /// Parses the optional amount after a keep/drop modifier, defaulting to 1.
+    fn keep_amount(&mut self, count: u32) -> Result<u32, DiceError> {
+        let keep = self.number()?.unwrap_or(1);
+        if keep > count {
+            return Err(DiceError::InvalidKeep { keep, count });
+        }
+        Ok(keep)
+    }
+}
+
+impl fmt::Display for DiceTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.keep {
+            Some(Keep::Highest(n)) => write!(f, "kh{n}")?,
+            Some(Keep::Lowest(n)) => write!(f, "kl{n}")?,
+            None => {}
+        }
+        if self.explode {
+            write!(f, "!")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TermKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TermKind::Constant(value) => write!(f, "{value}"),
+            TermKind::Dice(dice) => write!(f, "{dice}"),
+        }
+    }
+}
+
+impl fmt::Display for DiceExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            match (i, term.negative) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            write!(f, "{}", term.kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DieRoll {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.kept, self.exploded) {
+            (false, _) => write!(f, "~{}~", self.value),
+            (true, true) => write!(f, "{}!", self.value),
+            (true, false) => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl fmt::Display for RollResult {
+    /// Formats the per-die breakdown, e.g. `4d6kh3[6, 5, 3, ~1~] + 2 = 16`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, result) in self.terms.iter().enumerate() {
+            match (i, result.term.negative) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            match &result.term.kind {
+                TermKind::Constant(value) => write!(f, "{value}")?,
+                TermKind::Dice(dice) => write!(f, "{dice}[{}]", result.dice.iter().join(", "))?,
+            }
+        }
+        write!(f, " = {}", self.total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    fn dice(count: u32, sides: u32, keep: Option<Keep>, explode: bool) -> TermKind {
+        TermKind::Dice(DiceTerm {
+            count,
+            sides,
+            keep,
+            explode,
+        })
+    }
+
+    #[test]
+    fn test_parse_simple_expressions() {
+        let expr: DiceExpr = "2d6+3".parse().unwrap();
+        assert_eq!(expr.terms.len(), 2);
+        assert_eq!(expr.terms[0].kind, dice(2, 6, None, false));
+        assert_eq!(expr.terms[1].kind, TermKind::Constant(3));
+
+        let expr: DiceExpr = "1d8 + 1d6 - 2".parse().unwrap();
+        assert_eq!(expr.terms.len(), 3);
+        assert!(expr.terms[2].negative);
+
+        let expr: DiceExpr = "d20".parse().unwrap();
+        assert_eq!(expr.terms[0].kind, dice(1, 20, None, false));
+
+        let expr: DiceExpr = "12".parse().unwrap();
+        assert!(expr.is_constant());
+    }
+
+    #[test]
+    fn test_parse_modifiers() {
+        let expr: DiceExpr = "4d6kh3".parse().unwrap();
+        assert_eq!(
+            expr.terms[0].kind,
+            dice(4, 6, Some(Keep::Highest(3)), false)
+        );
+
+        let expr: DiceExpr = "4d6dl1".parse().unwrap();
+        assert_eq!(
+            expr.terms[0].kind,
+            dice(4, 6, Some(Keep::Highest(3)), false)
+        );
+
+        let expr: DiceExpr = "1d20adv".parse().unwrap();
+        assert_eq!(
+            expr.terms[0].kind,
+            dice(2, 20, Some(Keep::Highest(1)), false)
+        );
+
+        let expr: DiceExpr = "1d20dis".parse().unwrap();
+        assert_eq!(
+            expr.terms[0].kind,
+            dice(2, 20, Some(Keep::Lowest(1)), false)
+        );
+
+        let expr: DiceExpr = "8d6!".parse().unwrap();
+        assert_eq!(expr.terms[0].kind, dice(8, 6, None, true));
+
+        let expr: DiceExpr = "d%".parse().unwrap();
+        assert_eq!(expr.terms[0].kind, dice(1, 100, None, false));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("".parse::<DiceExpr>(), Err(DiceError::Empty));
+        assert_eq!("   ".parse::<DiceExpr>(), Err(DiceError::Empty));
+        assert_eq!("2d0".parse::<DiceExpr>(), Err(DiceError::NoSides));
+        assert_eq!(
+            "1d1!".parse::<DiceExpr>(),
+            Err(DiceError::InvalidExplode(1))
+        );
+        assert_eq!(
+            "2d6kh3".parse::<DiceExpr>(),
+            Err(DiceError::InvalidKeep { keep: 3, count: 2 })
+        );
+        assert_eq!("5000d6".parse::<DiceExpr>(), Err(DiceError::TooManyDice));
+        assert_eq!(
+            "3000000000d6adv".parse::<DiceExpr>(),
+            Err(DiceError::TooManyDice)
+        );
+        assert_eq!(
+            "2d20adv".parse::<DiceExpr>(),
+            Err(DiceError::InvalidAdvantage(4))
+        );
+        assert_eq!(
+            "1d20 + 2d6 dis".parse::<DiceExpr>(),
+            Err(DiceError::InvalidAdvantage(11))
+        );
+        assert_eq!(
+            "1d3000000000".parse::<DiceExpr>(),
+            Err(DiceError::TooManySides)
+        );
+        assert_eq!(
+            "1000d2000000000".parse::<DiceExpr>(),
+            Err(DiceError::TooManySides)
+        );
+        assert_eq!(
+            "1d20advadv".parse::<DiceExpr>(),
+            Err(DiceError::MultipleKeep(7))
+        );
+        assert_eq!(
+            "1d20advdis".parse::<DiceExpr>(),
+            Err(DiceError::MultipleKeep(7))
+        );
+        assert_eq!(
+            "4d6kh3dl1".parse::<DiceExpr>(),
+            Err(DiceError::MultipleKeep(6))
+        );
+        assert!(matches!(
+            "2d6+x".parse::<DiceExpr>(),
+            Err(DiceError::UnexpectedChar { found: 'x', .. })
+        ));
+        assert!("2d".parse::<DiceExpr>().is_err());
+        // positions count the whitespace the user typed
+        assert_eq!(
+            "2d6 + x".parse::<DiceExpr>(),
+            Err(DiceError::UnexpectedChar {
+                found: 'x',
+                position: 6
+            })
+        );
+        assert_eq!(
+            "1d20 adv adv".parse::<DiceExpr>(),
+            Err(DiceError::MultipleKeep(9))
+        );
+        assert_eq!(
+            "2d6 + ".parse::<DiceExpr>(),
+            Err(DiceError::ExpectedNumber(6))
+        );
+        assert!("2d6+".parse::<DiceExpr>().is_err());
+    }
+
+    #[test]
+    fn test_roll_is_deterministic_with_seed() {
+        let expr: DiceExpr = "1d8+1d6+2".parse().unwrap();
+        let first = expr.roll(&mut StdRng::seed_from_u64(42)).unwrap();
+        let second = expr.roll(&mut StdRng::seed_from_u64(42)).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.total, first.terms[0].value + first.terms[1].value + 2);
+    }
+
+    #[test]
+    fn test_roll_overflow_is_an_error() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(
+            roll_expression(&mut rng, "2147483647+1"),
+            Err(DiceError::Overflow)
+        );
+        assert_eq!(
+            roll_expression(&mut rng, "-2147483647-1d1-1"),
+            Err(DiceError::Overflow)
+        );
+        let result = roll_expression(&mut rng, "1000d1000!").unwrap();
+        assert!(result.total >= 1000);
+        let result = roll_expression(&mut rng, "2147483647-1d1").unwrap();
+        assert_eq!(result.total, 2147483646);
+    }
+
+    #[test]
+    fn test_roll_keep_and_explode() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let result = roll_expression(&mut rng, "4d6kh3").unwrap();
+            let dice = &result.terms[0].dice;
+            assert_eq!(dice.len(), 4);
+            assert_eq!(dice.iter().filter(|d| d.kept).count(), 3);
+            let dropped = dice.iter().find(|d| !d.kept).unwrap();
+            assert!(dice
+                .iter()
+                .filter(|d| d.kept)
+                .all(|d| d.value >= dropped.value));
+            assert!((3..=18).contains(&result.total));
+
+            let result = roll_expression(&mut rng, "2d6!").unwrap();
+            let dice = &result.terms[0].dice;
+            assert_eq!(dice.iter().filter(|d| !d.exploded).count(), 2);
+            assert!(dice.iter().filter(|d| d.exploded).all(|d| d.value == 6));
+            assert_eq!(result.total, dice.iter().map(|d| d.value).sum::<i32>());
+        }
+    }
+
+    #[test]
+    fn test_roll_dice_matches_single_die() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let roll = roll_dice(&mut rng, 20, -2);
+            assert!((-1..=18).contains(&roll));
+        }
+    }
+
+    #[test]
+    fn test_critical() {
+        let critical = |expr: &str| expr.parse::<DiceExpr>().unwrap().critical();
+        assert_eq!(critical("1d8+3").unwrap().to_string(), "2d8 + 3");
+        assert_eq!(
+            critical("2d6 + 1d8 - 1").unwrap().to_string(),
+            "4d6 + 2d8 - 1"
+        );
+        assert_eq!(critical("5").unwrap().to_string(), "5");
+        // the doubled dice are limited like any other term
+        assert_eq!(critical("500d6").unwrap().to_string(), "1000d6");
+        assert_eq!(critical("1000d6"), Err(DiceError::TooManyDice));
+    }
+
+    #[test]
+    fn test_display_breakdown() {
+        let expr: DiceExpr = "2d6 - 1".parse().unwrap();
+        assert_eq!(expr.to_string(), "2d6 - 1");
+        let result = expr.roll(&mut StdRng::seed_from_u64(3)).unwrap();
+        let breakdown = result.to_string();
+        assert!(breakdown.starts_with("2d6["));
+        assert!(breakdown.ends_with(&format!(" - 1 = {}", result.total)));
+    }
 }
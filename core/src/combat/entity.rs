@@ -1,9 +1,19 @@
 #![allow(dead_code)]
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fmt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
 use uuid::Uuid;
 
+use crate::combat::{
+    damage::{split_damage_type, DamageModifier, DamageType},
+    dice::DiceExpr,
+    stats::{ability_modifier, Ability, Skill, DEFAULT_PROFICIENCY_BONUS},
+};
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Condition {
     Blinded,
@@ -30,15 +40,356 @@ impl fmt::Display for Condition {
     }
 }
 
+/// How long an applied condition lasts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionDuration {
+    /// Counted down at the end of each of the affected entity's turns
+    Rounds(u32),
+    /// Until the end of the next turn of the entity that applied the condition.
+    /// `skip_current` is set if it was applied during the source's own turn.
+    EndOfSourceTurn { source: Uuid, skip_current: bool },
+    /// Until the entity succeeds on a saving throw, removed by hand
+    UntilSave,
+}
+
+impl ConditionDuration {
+    /// Advances the duration at the end of `ending`'s turn, `owner` is the affected entity.
+    /// Returns false once the duration ran out.
+    pub fn tick(&mut self, ending: Uuid, owner: Uuid) -> bool {
+        match self {
+            ConditionDuration::Rounds(rounds) if owner == ending => {
+                *rounds = rounds.saturating_sub(1);
+                *rounds > 0
+            }
+            ConditionDuration::EndOfSourceTurn {
+                source,
+                skip_current,
+            } if *source == ending => std::mem::take(skip_current),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for ConditionDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConditionDuration::Rounds(rounds) => write!(f, "{rounds}"),
+            ConditionDuration::EndOfSourceTurn { .. } => write!(f, "EoT"),
+            ConditionDuration::UntilSave => write!(f, "save"),
+        }
+    }
+}
+
+/// A user-defined named effect like Bless, Hunter's Mark or Exhaustion 3
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Effect {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<ConditionDuration>,
+    /// The entity that applied the effect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Uuid>,
+    /// Reminders for the affected entity while the effect lasts, e.g. Searing Smite damage
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Trigger>,
+}
+
+impl Effect {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: None,
+            duration: None,
+            source: None,
+            triggers: Vec::new(),
+        }
+    }
+}
+
+/// When a [`Trigger`] fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TurnPhase {
+    #[serde(alias = "start")]
+    Start,
+    #[serde(alias = "end")]
+    End,
+}
+
+impl fmt::Display for TurnPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TurnPhase::Start => write!(f, "start of turn"),
+            TurnPhase::End => write!(f, "end of turn"),
+        }
+    }
+}
+
+/// A reminder shown at the start or end of the entity's turn, e.g. a WIS save vs Hold Person.
+/// Triggers with `heal` or `damage` are rolled and applied automatically, e.g. regeneration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub when: TurnPhase,
+    pub text: String,
+    /// Dice expression that is healed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heal: Option<String>,
+    /// Dice expression with an optional damage type, e.g. `2d6 fire`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage: Option<String>,
+}
+
+impl Trigger {
+    pub fn new(when: TurnPhase, text: &str) -> Self {
+        Self {
+            when,
+            text: text.to_string(),
+            heal: None,
+            damage: None,
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.when, self.text)?;
+        if let Some(heal) = &self.heal {
+            write!(f, " (heal {heal})")?;
+        }
+        if let Some(damage) = &self.damage {
+            write!(f, " ({damage} damage)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `start|end <text> [heal <dice>|damage <dice> [type]]`,
+/// e.g. `start Regenerate heal 10` or `end Make a WIS save vs Hold Person`
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = "expected 'start' or 'end' followed by the reminder";
+        let (when, rest) = s.trim().split_once(char::is_whitespace).ok_or(usage)?;
+        let when = match when.to_lowercase().as_str() {
+            "start" => TurnPhase::Start,
+            "end" => TurnPhase::End,
+            _ => return Err(usage.to_string()),
+        };
+        let words: Vec<&str> = rest.split_whitespace().collect();
+        let keyword = words
+            .iter()
+            .rposition(|w| w.eq_ignore_ascii_case("heal") || w.eq_ignore_ascii_case("damage"))
+            .filter(|&i| i + 1 < words.len());
+        let Some(i) = keyword else {
+            return Ok(Trigger::new(when, &words.join(" ")));
+        };
+        let amount = words[i + 1..].join(" ");
+        let (dice, _) = split_damage_type(&amount);
+        dice.parse::<DiceExpr>().map_err(|e| e.to_string())?;
+        // without a description the amount is the reminder, e.g. `start heal 10`
+        let text = if i == 0 {
+            words.join(" ")
+        } else {
+            words[..i].join(" ")
+        };
+        let mut trigger = Trigger::new(when, &text);
+        if words[i].eq_ignore_ascii_case("heal") {
+            trigger.heal = Some(amount);
+        } else {
+            trigger.damage = Some(amount);
+        }
+        Ok(trigger)
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(value) = self.value {
+            write!(f, " {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Death saving throws of a player character at 0 hit points
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeathSaves {
+    #[serde(default)]
+    pub successes: u8,
+    #[serde(default)]
+    pub failures: u8,
+    /// Stable characters stay at 0 hit points but don't roll death saves
+    #[serde(default)]
+    pub stable: bool,
+}
+
+impl DeathSaves {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for DeathSaves {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dots = |count: u8| (0..3).map(|i| if i < count { '●' } else { '○' }).join("");
+        write!(f, "S {} F {}", dots(self.successes), dots(self.failures))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeathSaveOutcome {
+    Success,
+    Failure,
+    Stabilized,
+    Died,
+    /// A natural 20, the character regains 1 hit point
+    Revived,
+}
+
+impl fmt::Display for DeathSaveOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeathSaveOutcome::Success => write!(f, "success"),
+            DeathSaveOutcome::Failure => write!(f, "failure"),
+            DeathSaveOutcome::Stabilized => write!(f, "stabilized"),
+            DeathSaveOutcome::Died => write!(f, "died"),
+            DeathSaveOutcome::Revived => write!(f, "regains 1 HP"),
+        }
+    }
+}
+
+/// An ability that can't be used every turn, like a Breath Weapon (Recharge 5–6)
+/// or a spell that can be cast 3/day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitedAbility {
+    pub name: String,
+    /// Lowest d6 roll that recharges it at the start of its owner's turn, e.g. 5 for Recharge 5–6
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recharge: Option<i32>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub per_day: u32,
+    /// Uses since the last recharge or long rest
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub used: u32,
+}
+
+impl LimitedAbility {
+    pub fn is_available(&self) -> bool {
+        if self.recharge.is_some() {
+            self.used == 0
+        } else if self.per_day > 0 {
+            self.used < self.per_day
+        } else {
+            true
+        }
+    }
+
+    /// Recharge abilities that were used wait for a successful recharge roll
+    pub fn needs_recharge(&self) -> bool {
+        self.recharge.is_some() && self.used > 0
+    }
+}
+
+/// `Breath Weapon ●` while available and `○` once spent, per-day uses show one dot each
+impl fmt::Display for LimitedAbility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dot = |available: bool| if available { '●' } else { '○' };
+        match self.recharge {
+            Some(_) => write!(f, "{} {}", self.name, dot(self.is_available())),
+            None if self.per_day > 0 => {
+                let dots = (0..self.per_day).map(|i| dot(i >= self.used)).join("");
+                write!(f, "{} {dots}", self.name)
+            }
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// An attack or other action of a stat block, e.g. `Scimitar +4, 1d6+2 slashing`
+/// or `Fire Breath DC 21 DEX, 18d6 fire`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Action {
+    pub name: String,
+    /// Attack bonus of attacks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_hit: Option<i32>,
+    /// Dice expression like `1d6+2`, it's validated when it's rolled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub damage_type: Option<DamageType>,
+    /// DC of the saving throw against it, e.g. of a breath weapon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_dc: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_ability: Option<Ability>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl Action {
+    /// The damage with its type, e.g. `1d6+2 slashing`
+    pub fn damage_string(&self) -> Option<String> {
+        let damage = self.damage.as_ref()?;
+        Some(match self.damage_type {
+            Some(damage_type) => format!("{damage} {damage_type}"),
+            None => damage.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(to_hit) = self.to_hit {
+            write!(f, " {to_hit:+}")?;
+        }
+        if let Some(dc) = self.save_dc {
+            write!(f, " DC {dc}")?;
+            if let Some(ability) = self.save_ability {
+                write!(f, " {ability}")?;
+            }
+        }
+        if let Some(damage) = self.damage_string() {
+            write!(f, ", {damage}")?;
+        }
+        Ok(())
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum EntityType {
     #[default]
     Player,
     Npc,
     Monster,
+    /// The synthetic step for lair actions on initiative count 20
+    Lair,
+}
+
+impl FromStr for EntityType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "player" | "pc" => Ok(EntityType::Player),
+            "npc" => Ok(EntityType::Npc),
+            "monster" => Ok(EntityType::Monster),
+            _ => Err(format!(
+                "unknown entity type '{s}', expected player, npc or monster"
+            )),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Entity {
     #[serde(default)]
     pub id: i32, // for multiple Monsters of the same type
@@ -54,7 +405,68 @@ pub struct Entity {
     #[serde(default)]
     pub current_hp: i32,
     #[serde(default)]
+    pub temp_hp: i32,
+    #[serde(default)]
     pub conditions: HashSet<Condition>,
+    /// Durations of conditions that don't last indefinitely
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub condition_durations: HashMap<Condition, ConditionDuration>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<Effect>,
+    /// The spell the entity is concentrating on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concentration: Option<String>,
+    #[serde(default, skip_serializing_if = "DeathSaves::is_empty")]
+    pub death_saves: DeathSaves,
+    /// Delayed its turn and is out of the rotation until it's resumed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub delaying: bool,
+    /// Position among entities with the same initiative, lower acts first.
+    /// Set when a delayed turn is resumed, so the order survives sorting and reloading.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub initiative_order: u32,
+    /// The action it readied and its trigger, it can be used until the start of its next turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readied: Option<String>,
+    /// Legendary actions per round, refreshed at the start of its turn
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub legendary_actions: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub legendary_actions_used: u32,
+    /// Lair actions taken on initiative count 20
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lair_actions: Vec<String>,
+    /// Attacks and other actions of the stat block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<Action>,
+    /// Abilities with a recharge or a number of uses per day
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub limited_abilities: Vec<LimitedAbility>,
+    /// Reminders and automatic effects at the start or end of its turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Trigger>,
+    /// STR to CHA, e.g. `{str: 8, dex: 14}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ability_scores: HashMap<Ability, i32>,
+    /// Saving throw modifiers, e.g. `{con: 3}`, they take precedence over derived ones
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub saving_throws: HashMap<Ability, i32>,
+    /// Saves that add the proficiency bonus to the ability modifier
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub save_proficiencies: HashSet<Ability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proficiency_bonus: Option<i32>,
+    /// Skill modifiers, e.g. `{stealth: 6}`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub skills: HashMap<Skill, i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passive_perception: Option<i32>,
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub resistances: HashSet<DamageType>,
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub vulnerabilities: HashSet<DamageType>,
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    pub immunities: HashSet<DamageType>,
 }
 
 impl Entity {
@@ -75,49 +487,409 @@ impl Entity {
             ac,
             max_hp,
             current_hp: max_hp,
+            temp_hp: 0,
             conditions: HashSet::new(),
+            condition_durations: HashMap::new(),
+            effects: Vec::new(),
+            concentration: None,
+            death_saves: DeathSaves::default(),
+            delaying: false,
+            initiative_order: 0,
+            readied: None,
+            legendary_actions: 0,
+            legendary_actions_used: 0,
+            lair_actions: Vec::new(),
+            actions: Vec::new(),
+            limited_abilities: Vec::new(),
+            triggers: Vec::new(),
+            ability_scores: HashMap::new(),
+            saving_throws: HashMap::new(),
+            save_proficiencies: HashSet::new(),
+            proficiency_bonus: None,
+            skills: HashMap::new(),
+            passive_perception: None,
+            resistances: HashSet::new(),
+            vulnerabilities: HashSet::new(),
+            immunities: HashSet::new(),
+        }
+    }
+
+    /// Applies immunities, resistances and vulnerabilities to an amount of damage.
+    /// Returns the adjusted amount and the modifiers that were applied.
+    pub fn adjust_damage(
+        &self,
+        amount: i32,
+        damage_type: Option<DamageType>,
+    ) -> (i32, Vec<DamageModifier>) {
+        let Some(damage_type) = damage_type else {
+            return (amount, Vec::new());
+        };
+        if self.immunities.contains(&damage_type) {
+            return (0, vec![DamageModifier::Immune]);
+        }
+        let mut amount = amount;
+        let mut modifiers = Vec::new();
+        // resistance is applied before vulnerability
+        if self.resistances.contains(&damage_type) {
+            amount /= 2;
+            modifiers.push(DamageModifier::Resistant);
+        }
+        if self.vulnerabilities.contains(&damage_type) {
+            amount = amount.saturating_mul(2);
+            modifiers.push(DamageModifier::Vulnerable);
         }
+        (amount, modifiers)
+    }
+
+    /// How much of `damage` the temporary hit points absorb
+    pub fn temp_hp_absorbs(&self, damage: i32) -> i32 {
+        damage.max(0).min(self.temp_hp.max(0))
+    }
+
+    /// Applies damage, temporary hit points are used up first.
+    /// Returns how much of the damage was absorbed by temporary hit points.
+    pub fn take_damage(&mut self, damage: i32) -> i32 {
+        let absorbed = self.temp_hp_absorbs(damage);
+        self.temp_hp -= absorbed;
+        self.current_hp = (self.current_hp - (damage - absorbed)).max(0);
+        absorbed
     }
 
-    pub fn take_damage(&mut self, damage: i32) {
-        self.current_hp = (self.current_hp - damage).max(0);
+    /// Temporary hit points don't stack, the higher value is kept
+    pub fn set_temp_hp(&mut self, amount: i32) {
+        self.temp_hp = self.temp_hp.max(amount);
     }
 
+    /// Heals the entity, regaining hit points resets the death saves
     pub fn heal(&mut self, amount: i32) {
-        self.current_hp = (self.current_hp + amount).min(self.max_hp);
+        self.current_hp = self.current_hp.saturating_add(amount).min(self.max_hp);
+        if self.current_hp > 0 {
+            self.death_saves = DeathSaves::default();
+        }
+    }
+
+    /// A player character at 0 hit points that still rolls death saves
+    pub fn is_dying(&self) -> bool {
+        self.entity_type == EntityType::Player
+            && self.current_hp == 0
+            && !self.death_saves.stable
+            && self.death_saves.failures < 3
+    }
+
+    /// Monsters die at 0 hit points, player characters after three failed death saves
+    pub fn is_dead(&self) -> bool {
+        match self.entity_type {
+            EntityType::Player => self.current_hp == 0 && self.death_saves.failures >= 3,
+            EntityType::Lair => false,
+            _ => self.current_hp == 0,
+        }
+    }
+
+    /// Resolves a death save with the natural d20 `roll`
+    pub fn death_save(&mut self, roll: i32) -> DeathSaveOutcome {
+        match roll {
+            20.. => {
+                self.current_hp = 1;
+                self.death_saves = DeathSaves::default();
+                DeathSaveOutcome::Revived
+            }
+            10..=19 => {
+                self.death_saves.successes += 1;
+                if self.death_saves.successes >= 3 {
+                    self.stabilize();
+                    DeathSaveOutcome::Stabilized
+                } else {
+                    DeathSaveOutcome::Success
+                }
+            }
+            2..=9 => self.fail_death_save(1),
+            _ => self.fail_death_save(2),
+        }
+    }
+
+    /// Adds failed death saves, e.g. for a natural 1 or taking damage at 0 hit points
+    pub fn fail_death_save(&mut self, count: u8) -> DeathSaveOutcome {
+        self.death_saves.stable = false;
+        self.death_saves.failures = (self.death_saves.failures + count).min(3);
+        if self.death_saves.failures >= 3 {
+            DeathSaveOutcome::Died
+        } else {
+            DeathSaveOutcome::Failure
+        }
+    }
+
+    pub fn stabilize(&mut self) {
+        self.death_saves = DeathSaves {
+            stable: true,
+            ..Default::default()
+        };
+    }
+
+    /// Drops the entity to 0 hit points, a player character dies instead of dying slowly
+    pub fn kill(&mut self) {
+        self.current_hp = 0;
+        self.temp_hp = 0;
+        if self.entity_type == EntityType::Player {
+            self.death_saves = DeathSaves {
+                failures: 3,
+                ..Default::default()
+            };
+        }
+    }
+
+    pub fn legendary_actions_remaining(&self) -> u32 {
+        self.legendary_actions
+            .saturating_sub(self.legendary_actions_used)
+    }
+
+    /// Its own triggers and the ones of its effects for `phase`,
+    /// together with the name of the effect they belong to
+    pub fn triggers(&self, phase: TurnPhase) -> Vec<(Option<String>, Trigger)> {
+        let own = self.triggers.iter().map(|t| (None, t.clone()));
+        let effects = self.effects.iter().flat_map(|effect| {
+            effect
+                .triggers
+                .iter()
+                .map(|t| (Some(effect.name.clone()), t.clone()))
+        });
+        own.chain(effects)
+            .filter(|(_, t)| t.when == phase)
+            .collect()
+    }
+
+    /// `Dying`, `Stable` or `Dead` for player characters at 0 hit points
+    pub fn death_save_string(&self) -> Option<String> {
+        if self.entity_type != EntityType::Player || self.current_hp > 0 {
+            None
+        } else if self.is_dead() {
+            Some("Dead".to_string())
+        } else if self.death_saves.stable {
+            Some("Stable".to_string())
+        } else {
+            Some(format!("Dying {}", self.death_saves))
+        }
     }
 
     pub fn add_condition(&mut self, condition: Condition) {
         self.conditions.insert(condition);
     }
 
+    pub fn add_condition_with_duration(
+        &mut self,
+        condition: Condition,
+        duration: Option<ConditionDuration>,
+    ) {
+        match duration {
+            Some(duration) => self.condition_durations.insert(condition.clone(), duration),
+            None => self.condition_durations.remove(&condition),
+        };
+        self.conditions.insert(condition);
+    }
+
     pub fn remove_condition(&mut self, condition: &Condition) {
         self.conditions.remove(condition);
+        self.condition_durations.remove(condition);
+    }
+
+    pub fn get_effect(&self, name: &str) -> Option<&Effect> {
+        self.effects
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Adds the effect, replacing an existing one with the same name
+    pub fn add_effect(&mut self, effect: Effect) {
+        match self
+            .effects
+            .iter_mut()
+            .find(|e| e.name.eq_ignore_ascii_case(&effect.name))
+        {
+            Some(existing) => *existing = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    pub fn remove_effect(&mut self, name: &str) -> Option<Effect> {
+        let index = self
+            .effects
+            .iter()
+            .position(|e| e.name.eq_ignore_ascii_case(name))?;
+        Some(self.effects.remove(index))
+    }
+
+    pub fn ability_modifier(&self, ability: Ability) -> Option<i32> {
+        self.ability_scores
+            .get(&ability)
+            .copied()
+            .map(ability_modifier)
+    }
+
+    /// The stored save modifier, otherwise the one derived from the ability score
+    pub fn save_modifier(&self, ability: Ability) -> Option<i32> {
+        if let Some(modifier) = self.saving_throws.get(&ability) {
+            return Some(*modifier);
+        }
+        let proficiency = if self.save_proficiencies.contains(&ability) {
+            self.proficiency_bonus.unwrap_or(DEFAULT_PROFICIENCY_BONUS)
+        } else {
+            0
+        };
+        Some(self.ability_modifier(ability)? + proficiency)
+    }
+
+    /// The stored skill modifier, otherwise the modifier of the skill's ability
+    pub fn skill_modifier(&self, skill: Skill) -> Option<i32> {
+        self.skills
+            .get(&skill)
+            .copied()
+            .or_else(|| self.ability_modifier(skill.ability()))
+    }
+
+    pub fn passive_perception(&self) -> Option<i32> {
+        self.passive_perception
+            .or_else(|| Some(10 + self.skill_modifier(Skill::Perception)?))
+    }
+
+    /// The stat block for the detail view, lines without any values are left out
+    pub fn details(&self) -> Vec<String> {
+        let mut summary = format!(
+            "{}  AC {}  HP {}/{}  Initiative {:+}",
+            self.display_name(),
+            self.ac,
+            self.current_hp,
+            self.max_hp,
+            self.initiative_modifier
+        );
+        if let Some(passive) = self.passive_perception() {
+            summary += &format!("  Passive Perception {passive}");
+        }
+        let mut lines = vec![summary];
+        if !self.ability_scores.is_empty() {
+            lines.push(
+                Ability::ALL
+                    .iter()
+                    .map(|ability| match self.ability_scores.get(ability) {
+                        Some(score) => {
+                            format!("{ability} {score} ({:+})", ability_modifier(*score))
+                        }
+                        None => format!("{ability} -"),
+                    })
+                    .join("  "),
+            );
+        }
+        let saves = Ability::ALL
+            .iter()
+            .filter(|a| self.saving_throws.contains_key(a) || self.save_proficiencies.contains(a))
+            .filter_map(|a| Some(format!("{a} {:+}", self.save_modifier(*a)?)))
+            .join(", ");
+        if !saves.is_empty() {
+            lines.push(format!("Saves: {saves}"));
+        }
+        if !self.skills.is_empty() {
+            let skills = self
+                .skills
+                .iter()
+                .sorted()
+                .map(|(skill, modifier)| format!("{skill} {modifier:+}"))
+                .join(", ");
+            lines.push(format!("Skills: {skills}"));
+        }
+        let damage = [
+            ("Resistances", &self.resistances),
+            ("Immunities", &self.immunities),
+            ("Vulnerabilities", &self.vulnerabilities),
+        ]
+        .into_iter()
+        .filter(|(_, types)| !types.is_empty())
+        .map(|(label, types)| format!("{label}: {}", types.iter().sorted().join(", ")))
+        .join("  ");
+        if !damage.is_empty() {
+            lines.push(damage);
+        }
+        lines
+    }
+
+    /// The conditions and effects with their remaining duration, e.g. `Stunned (2), Bless (10)`
+    pub fn conditions_string(&self) -> String {
+        let death_saves = self.death_save_string().into_iter();
+        let turn = self
+            .delaying
+            .then(|| "Delaying".to_string())
+            .into_iter()
+            .chain(
+                self.readied
+                    .iter()
+                    .map(|action| format!("Readied ({action})")),
+            );
+        let legendary = (self.legendary_actions > 0).then(|| {
+            format!(
+                "Legendary {}/{}",
+                self.legendary_actions_remaining(),
+                self.legendary_actions
+            )
+        });
+        let abilities = self.limited_abilities.iter().map(|a| a.to_string());
+        let concentration = self
+            .concentration
+            .iter()
+            .map(|spell| format!("Concentrating ({spell})"));
+        let conditions = self.conditions.iter().sorted().map(|condition| {
+            match self.condition_durations.get(condition) {
+                Some(duration) => format!("{condition} ({duration})"),
+                None => condition.to_string(),
+            }
+        });
+        let effects = self.effects.iter().map(|effect| match &effect.duration {
+            Some(duration) => format!("{effect} ({duration})"),
+            None => effect.to_string(),
+        });
+        death_saves
+            .chain(legendary)
+            .chain(abilities)
+            .chain(turn)
+            .chain(concentration)
+            .chain(conditions)
+            .chain(effects)
+            .join(", ")
     }
 
     pub fn is_alive(&self) -> bool {
         self.current_hp > 0
     }
 
-    pub fn ref_array_string(&self) -> Vec<String> {
-        // TODO this funciton should not be the responsibility of the Entity
-        let display_name = if self.id != 0 {
+    /// The name including the id when there are several entities with the same name
+    pub fn display_name(&self) -> String {
+        if self.id != 0 {
             format!("{} ({})", self.name, self.id)
         } else {
             self.name.to_string()
-        };
+        }
+    }
+
+    pub fn ref_array_string(&self) -> Vec<String> {
+        // TODO this funciton should not be the responsibility of the Entity
+        let display_name = self.display_name();
+        if self.entity_type == EntityType::Lair {
+            return vec![
+                self.initiative.unwrap_or_default().to_string(),
+                display_name,
+                String::new(),
+                String::new(),
+                self.conditions_string(),
+            ];
+        }
         vec![
             self.initiative
                 .expect("Initiative needs to be rolled.")
                 .to_string(),
             display_name,
-            format!(
-                "{}/{}",
-                self.current_hp.to_string(),
-                self.max_hp.to_string()
-            ),
+            if self.temp_hp > 0 {
+                format!("{}/{} +{}", self.current_hp, self.max_hp, self.temp_hp)
+            } else {
+                format!("{}/{}", self.current_hp, self.max_hp)
+            },
             self.ac.to_string(),
-            self.conditions.iter().join(", "),
+            self.conditions_string(),
         ]
     }
 }
@@ -0,0 +1,260 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use itertools::Itertools;
+
+use crate::combat::{
+    damage::{DamageModifier, DamageType},
+    entity::{Condition, DeathSaveOutcome},
+    stats::Ability,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CombatEvent {
+    Damage {
+        amount: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        damage_type: Option<DamageType>,
+        /// The amount before resistances etc. were applied, if it changed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        adjusted_from: Option<i32>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        modifiers: Vec<DamageModifier>,
+        hp_before: i32,
+        hp_after: i32,
+        #[serde(default)]
+        temp_hp_absorbed: i32,
+    },
+    Heal {
+        amount: i32,
+        hp_before: i32,
+        hp_after: i32,
+    },
+    TempHp {
+        amount: i32,
+        temp_hp_before: i32,
+        temp_hp_after: i32,
+    },
+    ConditionAdded(Condition),
+    ConditionRemoved(Condition),
+    ConditionExpired(Condition),
+    EffectAdded(String),
+    EffectRemoved(String),
+    EffectExpired(String),
+    ConcentrationStarted(String),
+    ConcentrationEnded(String),
+    SavingThrow {
+        ability: Ability,
+        roll: i32,
+        dc: i32,
+        success: bool,
+    },
+    /// `roll` is missing for failures caused by damage and for stabilizing by hand
+    DeathSave {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        roll: Option<i32>,
+        outcome: DeathSaveOutcome,
+        successes: u8,
+        failures: u8,
+    },
+    Joined {
+        initiative: i32,
+    },
+    Left,
+    Killed,
+    Delayed,
+    Resumed {
+        /// The entity it acts after
+        after: String,
+    },
+    Readied(String),
+    ReadiedUsed(String),
+    ReadiedExpired(String),
+    LegendaryAction {
+        cost: u32,
+        remaining: u32,
+    },
+    AbilityUsed(String),
+    AbilityRestored(String),
+    /// The d6 roll at the start of the turn for a spent recharge ability
+    Recharge {
+        ability: String,
+        roll: i32,
+        success: bool,
+    },
+    /// An attack roll against the AC of the target, `roll` is the natural d20 roll
+    Attack {
+        attacker: String,
+        roll: i32,
+        bonus: i32,
+        ac: i32,
+        hit: bool,
+    },
+    /// A start or end of turn trigger, e.g. `start of turn: Regenerate (heal 10)`
+    Triggered(String),
+    /// Free text, e.g. for undo/redo
+    Note(String),
+}
+
+/// One line of the combat log.
+/// `actor` is the entity whose turn it was, `target` the entity that was affected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub round: usize,
+    pub turn: usize,
+    #[serde(default)]
+    pub actor: Option<String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    pub event: CombatEvent,
+}
+
+impl fmt::Display for CombatEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CombatEvent::Damage {
+                amount,
+                damage_type,
+                adjusted_from,
+                modifiers,
+                hp_before,
+                hp_after,
+                temp_hp_absorbed,
+            } => {
+                write!(f, "{amount}")?;
+                if let Some(damage_type) = damage_type {
+                    write!(f, " {damage_type}")?;
+                }
+                write!(f, " damage")?;
+                if !modifiers.is_empty() {
+                    write!(f, " [{}", modifiers.iter().join(", "))?;
+                    if let Some(original) = adjusted_from {
+                        write!(f, " from {original}")?;
+                    }
+                    write!(f, "]")?;
+                }
+                write!(f, " ({hp_before} -> {hp_after} HP")?;
+                if *temp_hp_absorbed > 0 {
+                    write!(f, ", {temp_hp_absorbed} absorbed by temp HP")?;
+                }
+                write!(f, ")")
+            }
+            CombatEvent::Heal {
+                amount,
+                hp_before,
+                hp_after,
+            } => write!(f, "healed {amount} ({hp_before} -> {hp_after} HP)"),
+            CombatEvent::TempHp {
+                amount,
+                temp_hp_before,
+                temp_hp_after,
+            } => write!(
+                f,
+                "gains {amount} temp HP ({temp_hp_before} -> {temp_hp_after})"
+            ),
+            CombatEvent::ConditionAdded(condition) => write!(f, "+{condition}"),
+            CombatEvent::ConditionRemoved(condition) => write!(f, "-{condition}"),
+            CombatEvent::ConditionExpired(condition) => write!(f, "{condition} expired"),
+            CombatEvent::EffectAdded(effect) => write!(f, "+{effect}"),
+            CombatEvent::EffectRemoved(effect) => write!(f, "-{effect}"),
+            CombatEvent::EffectExpired(effect) => write!(f, "{effect} expired"),
+            CombatEvent::ConcentrationStarted(spell) => write!(f, "concentrating on {spell}"),
+            CombatEvent::ConcentrationEnded(spell) => write!(f, "lost concentration on {spell}"),
+            CombatEvent::SavingThrow {
+                ability,
+                roll,
+                dc,
+                success,
+            } => write!(
+                f,
+                "{ability} save {roll} vs DC {dc}: {}",
+                if *success { "success" } else { "failure" }
+            ),
+            CombatEvent::DeathSave {
+                roll,
+                outcome,
+                successes,
+                failures,
+            } => {
+                match (roll, outcome) {
+                    (Some(roll), outcome) => write!(f, "death save {roll}: {outcome}")?,
+                    (None, DeathSaveOutcome::Stabilized) => write!(f, "stabilized")?,
+                    (None, outcome) => write!(f, "damage at 0 HP, death save {outcome}")?,
+                }
+                if matches!(
+                    outcome,
+                    DeathSaveOutcome::Success | DeathSaveOutcome::Failure
+                ) {
+                    write!(f, " ({successes}/3 successes, {failures}/3 failures)")?;
+                }
+                Ok(())
+            }
+            CombatEvent::Joined { initiative } => {
+                write!(f, "joins the combat (initiative {initiative})")
+            }
+            CombatEvent::Left => write!(f, "leaves the combat"),
+            CombatEvent::Killed => write!(f, "is killed"),
+            CombatEvent::Delayed => write!(f, "delays its turn"),
+            CombatEvent::Resumed { after } => write!(f, "acts after {after}"),
+            CombatEvent::Readied(action) => write!(f, "readies {action}"),
+            CombatEvent::ReadiedUsed(action) => write!(f, "uses readied {action}"),
+            CombatEvent::ReadiedExpired(action) => write!(f, "readied {action} expired"),
+            CombatEvent::LegendaryAction { cost, remaining } => match cost {
+                1 => write!(f, "uses a legendary action ({remaining} left)"),
+                cost => write!(
+                    f,
+                    "uses a legendary action costing {cost} ({remaining} left)"
+                ),
+            },
+            CombatEvent::AbilityUsed(ability) => write!(f, "uses {ability}"),
+            CombatEvent::AbilityRestored(ability) => write!(f, "{ability} is available again"),
+            CombatEvent::Recharge {
+                ability,
+                roll,
+                success: true,
+            } => write!(f, "{ability} recharged (rolled {roll})"),
+            CombatEvent::Recharge {
+                ability,
+                roll,
+                success: false,
+            } => write!(f, "{ability} didn't recharge (rolled {roll})"),
+            CombatEvent::Attack {
+                attacker,
+                roll,
+                bonus,
+                ac,
+                hit,
+            } => {
+                let outcome = match (roll, hit) {
+                    (20, _) => "critical hit",
+                    (_, true) => "hit",
+                    (_, false) => "miss",
+                };
+                match roll {
+                    1 | 20 => write!(f, "attacked by {attacker}: natural {roll}, {outcome}"),
+                    roll => write!(
+                        f,
+                        "attacked by {attacker}: {} vs AC {ac}, {outcome}",
+                        roll.saturating_add(*bonus)
+                    ),
+                }
+            }
+            CombatEvent::Triggered(trigger) => write!(f, "{trigger}"),
+            CombatEvent::Note(note) => write!(f, "{note}"),
+        }
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "R{} T{}", self.round, self.turn + 1)?;
+        match (&self.actor, &self.target) {
+            (Some(actor), Some(target)) if actor != target => write!(f, " {actor} -> {target}:")?,
+            (_, Some(target)) => write!(f, " {target}:")?,
+            (Some(actor), None) => write!(f, " {actor}:")?,
+            (None, None) => write!(f, ":")?,
+        }
+        write!(f, " {}", self.event)
+    }
+}
@@ -1,3 +1,9 @@
-mod dice;
+pub mod bestiary;
+pub mod command;
+pub mod damage;
+pub mod dice;
 pub mod entity;
+pub mod log;
+pub mod open5e;
+pub mod stats;
 pub mod tracker;
@@ -0,0 +1,596 @@
+//! Import of monster stat blocks in the JSON schema of Open5e and the 5e-SRD API.
+//!
+//! Both describe a monster with `hit_points`, `armor_class`, the ability scores,
+//! `challenge_rating` and `actions`, but differ in the details:
+//! Open5e has the AC as a number and damage immunities as text,
+//! the 5e-SRD API a list of armor classes and lists of immunities.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::combat::{
+    damage::DamageType,
+    entity::{Action, Entity, EntityType, LimitedAbility},
+    stats::{ability_modifier, Ability, Skill},
+};
+
+/// Legendary actions of a monster whose description doesn't say how many it has
+const DEFAULT_LEGENDARY_ACTIONS: u32 = 3;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Open5eMonster {
+    pub name: String,
+    pub armor_class: ArmorClass,
+    pub hit_points: i32,
+    #[serde(default = "default_score")]
+    pub strength: i32,
+    #[serde(default = "default_score")]
+    pub dexterity: i32,
+    #[serde(default = "default_score")]
+    pub constitution: i32,
+    #[serde(default = "default_score")]
+    pub intelligence: i32,
+    #[serde(default = "default_score")]
+    pub wisdom: i32,
+    #[serde(default = "default_score")]
+    pub charisma: i32,
+    #[serde(default)]
+    pub strength_save: Option<i32>,
+    #[serde(default)]
+    pub dexterity_save: Option<i32>,
+    #[serde(default)]
+    pub constitution_save: Option<i32>,
+    #[serde(default)]
+    pub intelligence_save: Option<i32>,
+    #[serde(default)]
+    pub wisdom_save: Option<i32>,
+    #[serde(default)]
+    pub charisma_save: Option<i32>,
+    /// Saving throws and skills of the 5e-SRD API
+    #[serde(default)]
+    pub proficiencies: List<Proficiency>,
+    /// Skill modifiers of Open5e, e.g. `{"stealth": 6}`
+    #[serde(default)]
+    pub skills: Option<HashMap<String, i32>>,
+    /// Text with the passive Perception in Open5e, an object in the 5e-SRD API
+    #[serde(default)]
+    pub senses: Option<Senses>,
+    #[serde(default)]
+    pub damage_vulnerabilities: DamageList,
+    #[serde(default)]
+    pub damage_resistances: DamageList,
+    #[serde(default)]
+    pub damage_immunities: DamageList,
+    /// `"1/4"` in Open5e, `0.25` in the 5e-SRD API
+    #[serde(default)]
+    pub challenge_rating: Option<ChallengeRating>,
+    /// Only in the 5e-SRD API, otherwise it follows from the challenge rating
+    #[serde(default)]
+    pub proficiency_bonus: Option<i32>,
+    #[serde(default)]
+    pub actions: List<Open5eAction>,
+    #[serde(default)]
+    pub special_abilities: List<Open5eAction>,
+    #[serde(default)]
+    pub legendary_actions: List<Open5eAction>,
+    #[serde(default)]
+    pub legendary_desc: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ArmorClass {
+    Value(i32),
+    List(Vec<ArmorClassEntry>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ArmorClassEntry {
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ChallengeRating {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Senses {
+    Text(String),
+    Map { passive_perception: Option<i32> },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Proficiency {
+    pub value: i32,
+    pub proficiency: Reference,
+}
+
+/// A link to another resource of the 5e-SRD API, e.g. `{"index": "saving-throw-dex"}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reference {
+    pub index: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Open5eAction {
+    pub name: String,
+    #[serde(default)]
+    pub desc: String,
+    /// How often it can be used in the 5e-SRD API, Open5e puts it in the name
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    #[serde(default)]
+    pub attack_bonus: Option<i32>,
+    /// Damage dice of Open5e without the `damage_bonus`
+    #[serde(default)]
+    pub damage_dice: Option<String>,
+    #[serde(default)]
+    pub damage_bonus: Option<i32>,
+    /// Damage of the 5e-SRD API
+    #[serde(default)]
+    pub damage: List<SrdDamage>,
+    /// Saving throw of the 5e-SRD API
+    #[serde(default)]
+    pub dc: Option<SrdDc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SrdDamage {
+    #[serde(default)]
+    pub damage_type: Option<Reference>,
+    #[serde(default)]
+    pub damage_dice: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SrdDc {
+    pub dc_type: Reference,
+    pub dc_value: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Usage {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub min_value: Option<i32>,
+    #[serde(default)]
+    pub times: Option<u32>,
+}
+
+/// A list that's an empty string or `null` when there's nothing in it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum List<T> {
+    Items(Vec<T>),
+    Empty(IgnoredAny),
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        List::Items(Vec::new())
+    }
+}
+
+impl<T> List<T> {
+    pub fn items(&self) -> &[T] {
+        match self {
+            List::Items(items) => items,
+            List::Empty(_) => &[],
+        }
+    }
+}
+
+/// Damage types as text like `"cold; bludgeoning, piercing, and slashing from nonmagical attacks"`
+/// or as a list of such texts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DamageList {
+    Text(String),
+    List(Vec<String>),
+    Empty(IgnoredAny),
+}
+
+impl Default for DamageList {
+    fn default() -> Self {
+        DamageList::Text(String::new())
+    }
+}
+
+impl DamageList {
+    /// The damage types that always apply.
+    /// Conditional ones like "from nonmagical attacks" are left to the DM.
+    pub fn damage_types(&self) -> HashSet<DamageType> {
+        let texts = match self {
+            DamageList::Text(text) => vec![text.as_str()],
+            DamageList::List(texts) => texts.iter().map(String::as_str).collect(),
+            DamageList::Empty(_) => Vec::new(),
+        };
+        texts
+            .into_iter()
+            .flat_map(|text| text.split(';'))
+            .filter(|part| !is_conditional(part))
+            .flat_map(|part| part.split(|c: char| !c.is_ascii_alphabetic()))
+            .filter_map(|word| word.parse().ok())
+            .collect()
+    }
+}
+
+fn is_conditional(text: &str) -> bool {
+    let text = text.to_lowercase();
+    ["nonmagical", "from", "while", "that aren't", "except"]
+        .iter()
+        .any(|condition| text.contains(condition))
+}
+
+fn default_score() -> i32 {
+    10
+}
+
+impl Open5eMonster {
+    pub fn ac(&self) -> i32 {
+        match &self.armor_class {
+            ArmorClass::Value(ac) => *ac,
+            ArmorClass::List(entries) => entries.first().map_or(10, |entry| entry.value),
+        }
+    }
+
+    pub fn saving_throws(&self) -> HashMap<Ability, i32> {
+        let open5e = [
+            (Ability::Str, self.strength_save),
+            (Ability::Dex, self.dexterity_save),
+            (Ability::Con, self.constitution_save),
+            (Ability::Int, self.intelligence_save),
+            (Ability::Wis, self.wisdom_save),
+            (Ability::Cha, self.charisma_save),
+        ]
+        .into_iter()
+        .filter_map(|(ability, save)| Some((ability, save?)));
+        let srd = self.proficiencies.items().iter().filter_map(|p| {
+            let ability = p.proficiency.index.strip_prefix("saving-throw-")?;
+            Some((ability.parse().ok()?, p.value))
+        });
+        open5e.chain(srd).collect()
+    }
+
+    pub fn ability_scores(&self) -> HashMap<Ability, i32> {
+        HashMap::from([
+            (Ability::Str, self.strength),
+            (Ability::Dex, self.dexterity),
+            (Ability::Con, self.constitution),
+            (Ability::Int, self.intelligence),
+            (Ability::Wis, self.wisdom),
+            (Ability::Cha, self.charisma),
+        ])
+    }
+
+    pub fn skills(&self) -> HashMap<Skill, i32> {
+        let open5e = self
+            .skills
+            .iter()
+            .flatten()
+            .filter_map(|(skill, modifier)| Some((skill.parse().ok()?, *modifier)));
+        let srd = self.proficiencies.items().iter().filter_map(|p| {
+            let skill = p.proficiency.index.strip_prefix("skill-")?;
+            Some((skill.parse().ok()?, p.value))
+        });
+        open5e.chain(srd).collect()
+    }
+
+    /// From `"darkvision 60 ft., passive Perception 9"` or `{"passive_perception": 9}`
+    pub fn passive_perception(&self) -> Option<i32> {
+        match self.senses.as_ref()? {
+            Senses::Text(senses) => {
+                let senses = senses.to_lowercase();
+                let (_, rest) = senses.split_once("passive perception")?;
+                rest.split(|c: char| !c.is_ascii_digit())
+                    .find(|number| !number.is_empty())?
+                    .parse()
+                    .ok()
+            }
+            Senses::Map { passive_perception } => *passive_perception,
+        }
+    }
+
+    /// The given proficiency bonus or the one of the challenge rating:
+    /// +2 up to CR 4 and +1 for every 4 CR above that
+    pub fn proficiency_bonus(&self) -> Option<i32> {
+        if let Some(bonus) = self.proficiency_bonus {
+            return Some(bonus);
+        }
+        let challenge_rating = match self.challenge_rating.as_ref()? {
+            ChallengeRating::Number(cr) => *cr,
+            ChallengeRating::Text(cr) => match cr.split_once('/') {
+                Some((numerator, denominator)) => {
+                    numerator.trim().parse::<f64>().ok()?
+                        / denominator.trim().parse::<f64>().ok()?
+                }
+                None => cr.trim().parse().ok()?,
+            },
+        };
+        if !challenge_rating.is_finite() || challenge_rating < 0.0 {
+            return None;
+        }
+        Some(2 + (challenge_rating.ceil() as i32 - 1).max(0) / 4)
+    }
+
+    /// The number in "can take 3 legendary actions"
+    pub fn legendary_action_count(&self) -> u32 {
+        if self.legendary_actions.items().is_empty() {
+            return 0;
+        }
+        self.legendary_desc
+            .as_deref()
+            .and_then(|desc| {
+                let (_, rest) = desc.split_once("can take ")?;
+                rest.split_whitespace().next()?.parse().ok()
+            })
+            .unwrap_or(DEFAULT_LEGENDARY_ACTIONS)
+    }
+
+    pub fn actions(&self) -> Vec<Action> {
+        self.actions
+            .items()
+            .iter()
+            .map(Open5eAction::action)
+            .collect()
+    }
+
+    /// Actions and traits that recharge or can be used a number of times per day
+    pub fn limited_abilities(&self) -> Vec<LimitedAbility> {
+        self.actions
+            .items()
+            .iter()
+            .chain(self.special_abilities.items())
+            .filter_map(Open5eAction::limited_ability)
+            .collect()
+    }
+}
+
+impl Open5eAction {
+    /// The name without a note like `(Recharge 5-6)`, and the note
+    fn split_name(&self) -> (&str, &str) {
+        match self.name.split_once(" (") {
+            Some((name, note)) => (name.trim(), note.trim_end_matches(')')),
+            None => (self.name.trim(), ""),
+        }
+    }
+
+    /// Takes the numbers from the fields and reads what's missing from the description,
+    /// like the damage type in `Hit: 5 (1d6 + 2) slashing damage`
+    /// or the save in `must make a DC 21 Dexterity saving throw`
+    pub fn action(&self) -> Action {
+        let srd_damage = self.damage.items().first();
+        let damage = match (&self.damage_dice, self.damage_bonus) {
+            (Some(dice), Some(bonus)) if bonus != 0 => Some(format!("{dice}{bonus:+}")),
+            (Some(dice), _) => Some(dice.clone()),
+            (None, _) => srd_damage.and_then(|d| d.damage_dice.clone()),
+        };
+        let damage_type = srd_damage
+            .and_then(|d| d.damage_type.as_ref())
+            .and_then(|t| t.index.parse().ok())
+            .or_else(|| damage_type_in(&self.desc));
+        let save = match &self.dc {
+            Some(dc) => Some((dc.dc_value, dc.dc_type.index.parse().ok())),
+            None => save_in(&self.desc),
+        };
+        Action {
+            name: self.split_name().0.to_string(),
+            to_hit: self.attack_bonus,
+            damage,
+            damage_type,
+            save_dc: save.map(|(dc, _)| dc),
+            save_ability: save.and_then(|(_, ability)| ability),
+            description: self.desc.clone(),
+        }
+    }
+
+    /// Reads the usage from the 5e-SRD `usage` or from a name like
+    /// `Fire Breath (Recharge 5-6)` or `Legendary Resistance (3/Day)`
+    pub fn limited_ability(&self) -> Option<LimitedAbility> {
+        let (name, note) = self.split_name();
+        let mut ability = LimitedAbility {
+            name: name.to_string(),
+            recharge: None,
+            per_day: 0,
+            used: 0,
+        };
+        match &self.usage {
+            Some(usage) if usage.kind == "recharge on roll" => ability.recharge = usage.min_value,
+            Some(usage) if usage.kind == "per day" => ability.per_day = usage.times?,
+            _ => {
+                if let Some(range) = note.strip_prefix("Recharge ") {
+                    let min = range.split(['-', '–']).next()?;
+                    ability.recharge = Some(min.trim().parse().ok()?);
+                } else if let Some((times, _)) = note.split_once("/Day") {
+                    ability.per_day = times.trim().parse().ok()?;
+                } else {
+                    return None;
+                }
+            }
+        }
+        Some(ability)
+    }
+}
+
+impl From<Open5eMonster> for Entity {
+    fn from(monster: Open5eMonster) -> Self {
+        let mut entity = Entity::new(
+            &monster.name,
+            EntityType::Monster,
+            ability_modifier(monster.dexterity),
+            monster.ac(),
+            monster.hit_points,
+        );
+        entity.ability_scores = monster.ability_scores();
+        entity.saving_throws = monster.saving_throws();
+        entity.skills = monster.skills();
+        entity.passive_perception = monster.passive_perception();
+        entity.proficiency_bonus = monster.proficiency_bonus();
+        entity.vulnerabilities = monster.damage_vulnerabilities.damage_types();
+        entity.resistances = monster.damage_resistances.damage_types();
+        entity.immunities = monster.damage_immunities.damage_types();
+        entity.legendary_actions = monster.legendary_action_count();
+        entity.actions = monster.actions();
+        entity.limited_abilities = monster.limited_abilities();
+        entity
+    }
+}
+
+/// The type of the first `<type> damage` in `desc`
+fn damage_type_in(desc: &str) -> Option<DamageType> {
+    let words: Vec<&str> = desc
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+    words.windows(2).find_map(|pair| match pair {
+        [word, "damage"] => word.parse().ok(),
+        _ => None,
+    })
+}
+
+/// The DC and ability of `DC 21 Dexterity saving throw` in `desc`
+fn save_in(desc: &str) -> Option<(i32, Option<Ability>)> {
+    let (_, rest) = desc.split_once("DC ")?;
+    let mut words = rest.split_whitespace();
+    let dc = words
+        .next()?
+        .trim_end_matches(|c: char| !c.is_ascii_digit());
+    let ability = words.next().and_then(|word| word.parse().ok());
+    Some((dc.parse().ok()?, ability))
+}
+
+/// True if `value` looks like an Open5e or 5e-SRD stat block instead of an [`Entity`]
+pub fn is_open5e(value: &serde_yml::Value) -> bool {
+    value.get("hit_points").is_some() && value.get("max_hp").is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Entity {
+        serde_json::from_str::<Open5eMonster>(json).unwrap().into()
+    }
+
+    #[test]
+    fn test_open5e_fixtures() {
+        let goblin = parse(include_str!("../../fixtures/open5e/goblin.json"));
+        assert_eq!(goblin.name, "Goblin");
+        assert_eq!(goblin.entity_type, EntityType::Monster);
+        assert_eq!((goblin.ac, goblin.max_hp, goblin.current_hp), (15, 7, 7));
+        assert_eq!(goblin.initiative_modifier, 2);
+        assert!(goblin.saving_throws.is_empty());
+        assert_eq!(goblin.ability_scores[&Ability::Dex], 14);
+        assert_eq!(goblin.save_modifier(Ability::Wis), Some(-1));
+        assert_eq!(goblin.skills, HashMap::from([(Skill::Stealth, 6)]));
+        assert_eq!(goblin.passive_perception, Some(9));
+        assert_eq!(goblin.proficiency_bonus, Some(2));
+        assert!(goblin.immunities.is_empty());
+        assert_eq!(goblin.legendary_actions, 0);
+        assert!(goblin.limited_abilities.is_empty());
+        let actions = goblin.actions.iter().map(|a| a.to_string());
+        assert_eq!(
+            actions.collect::<Vec<_>>(),
+            vec!["Scimitar +4, 1d6+2 slashing", "Shortbow +4, 1d6+2 piercing"]
+        );
+
+        let dragon = parse(include_str!("../../fixtures/open5e/adult-red-dragon.json"));
+        assert_eq!((dragon.ac, dragon.max_hp), (19, 256));
+        assert_eq!(dragon.initiative_modifier, 0);
+        assert_eq!(dragon.saving_throws.len(), 4);
+        assert_eq!(dragon.saving_throws[&Ability::Con], 13);
+        assert_eq!(dragon.immunities, HashSet::from([DamageType::Fire]));
+        assert_eq!(dragon.legendary_actions, 3);
+        assert_eq!(dragon.proficiency_bonus, Some(6));
+        let abilities = dragon.limited_abilities.iter().map(|a| a.to_string());
+        assert_eq!(
+            abilities.collect::<Vec<_>>(),
+            vec!["Fire Breath ●", "Legendary Resistance ●●●"]
+        );
+        assert_eq!(dragon.limited_abilities[0].recharge, Some(5));
+        let actions = dragon.actions.iter().map(|a| a.to_string());
+        assert_eq!(
+            actions.collect::<Vec<_>>(),
+            vec![
+                "Multiattack",
+                "Bite +14, 2d10+2d6+8 piercing",
+                "Claw +14, 2d6+8 slashing",
+                "Fire Breath DC 21 DEX, 18d6 fire",
+            ]
+        );
+        // the ability of the same name is used when the action is rolled
+        assert_eq!(dragon.actions[3].name, dragon.limited_abilities[0].name);
+    }
+
+    #[test]
+    fn test_srd_fixtures() {
+        let werewolf = parse(include_str!("../../fixtures/5e-srd/werewolf.json"));
+        assert_eq!((werewolf.ac, werewolf.max_hp), (12, 58));
+        assert_eq!(werewolf.initiative_modifier, 1);
+        // only against nonmagical weapons, that's up to the DM
+        assert!(werewolf.immunities.is_empty());
+        assert!(werewolf.saving_throws.is_empty());
+        assert_eq!(werewolf.skills[&Skill::Perception], 4);
+        assert_eq!(werewolf.passive_perception(), Some(14));
+        assert_eq!(werewolf.proficiency_bonus, Some(2));
+        assert_eq!(werewolf.actions[0].to_string(), "Bite +4, 1d8+2 piercing");
+        assert!(werewolf.actions[0]
+            .description
+            .starts_with("Melee Weapon Attack"));
+
+        let monsters: Vec<Open5eMonster> =
+            serde_json::from_str(include_str!("../../fixtures/5e-srd/monsters.json")).unwrap();
+        let monsters: Vec<Entity> = monsters.into_iter().map(Entity::from).collect();
+        let dragon = &monsters[0];
+        assert_eq!(dragon.saving_throws[&Ability::Dex], 5);
+        assert_eq!(dragon.immunities, HashSet::from([DamageType::Acid]));
+        assert_eq!(dragon.limited_abilities[0].name, "Acid Breath");
+        assert_eq!(dragon.limited_abilities[0].recharge, Some(5));
+        assert_eq!(dragon.proficiency_bonus, Some(3));
+        let mage = &monsters[1];
+        assert_eq!(mage.initiative_modifier, 2);
+        assert_eq!(mage.limited_abilities[0].per_day, 2);
+    }
+
+    #[test]
+    fn test_proficiency_bonus_from_challenge_rating() {
+        let bonus = |challenge_rating: &str| {
+            let json = format!(
+                r#"{{"name": "Test", "hit_points": 1, "armor_class": 10, "challenge_rating": {challenge_rating}}}"#
+            );
+            serde_json::from_str::<Open5eMonster>(&json)
+                .unwrap()
+                .proficiency_bonus()
+        };
+        assert_eq!(bonus(r#""0""#), Some(2));
+        assert_eq!(bonus(r#""1/8""#), Some(2));
+        assert_eq!(bonus("0.5"), Some(2));
+        assert_eq!(bonus("4"), Some(2));
+        assert_eq!(bonus("5"), Some(3));
+        assert_eq!(bonus(r#""12""#), Some(4));
+        assert_eq!(bonus("13"), Some(5));
+        assert_eq!(bonus("24"), Some(7));
+        assert_eq!(bonus("30"), Some(9));
+        assert_eq!(bonus(r#""1/0""#), None);
+        assert_eq!(bonus(r#""unknown""#), None);
+    }
+
+    #[test]
+    fn test_damage_list() {
+        let list = DamageList::Text(
+            "cold; bludgeoning, piercing, and slashing from nonmagical attacks".to_string(),
+        );
+        assert_eq!(list.damage_types(), HashSet::from([DamageType::Cold]));
+        let list = DamageList::List(vec!["fire".to_string(), "poison".to_string()]);
+        assert_eq!(
+            list.damage_types(),
+            HashSet::from([DamageType::Fire, DamageType::Poison])
+        );
+    }
+}
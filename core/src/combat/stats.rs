@@ -0,0 +1,200 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Ability {
+    #[serde(alias = "str", alias = "strength", alias = "STR")]
+    Str,
+    #[serde(alias = "dex", alias = "dexterity", alias = "DEX")]
+    Dex,
+    #[serde(alias = "con", alias = "constitution", alias = "CON")]
+    Con,
+    #[serde(alias = "int", alias = "intelligence", alias = "INT")]
+    Int,
+    #[serde(alias = "wis", alias = "wisdom", alias = "WIS")]
+    Wis,
+    #[serde(alias = "cha", alias = "charisma", alias = "CHA")]
+    Cha,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 6] = [
+        Ability::Str,
+        Ability::Dex,
+        Ability::Con,
+        Ability::Int,
+        Ability::Wis,
+        Ability::Cha,
+    ];
+}
+
+impl fmt::Display for Ability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_uppercase())
+    }
+}
+
+impl FromStr for Ability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ability = match s.trim().to_lowercase().as_str() {
+            "str" | "strength" => Ability::Str,
+            "dex" | "dexterity" => Ability::Dex,
+            "con" | "constitution" => Ability::Con,
+            "int" | "intelligence" => Ability::Int,
+            "wis" | "wisdom" => Ability::Wis,
+            "cha" | "charisma" => Ability::Cha,
+            _ => return Err(format!("unknown ability '{s}'")),
+        };
+        Ok(ability)
+    }
+}
+
+/// The DC of the Constitution save to keep concentrating after taking `damage`
+pub fn concentration_dc(damage: i32) -> i32 {
+    (damage / 2).max(10)
+}
+
+/// Proficiency bonus of entities that don't specify it, the one of levels 1–4 and CR 0–4
+pub const DEFAULT_PROFICIENCY_BONUS: i32 = 2;
+
+/// The modifier of an ability score, e.g. -1 for 8
+pub fn ability_modifier(score: i32) -> i32 {
+    (score - 10).div_euclid(2)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Skill {
+    #[serde(alias = "acrobatics")]
+    Acrobatics,
+    #[serde(alias = "animal_handling")]
+    AnimalHandling,
+    #[serde(alias = "arcana")]
+    Arcana,
+    #[serde(alias = "athletics")]
+    Athletics,
+    #[serde(alias = "deception")]
+    Deception,
+    #[serde(alias = "history")]
+    History,
+    #[serde(alias = "insight")]
+    Insight,
+    #[serde(alias = "intimidation")]
+    Intimidation,
+    #[serde(alias = "investigation")]
+    Investigation,
+    #[serde(alias = "medicine")]
+    Medicine,
+    #[serde(alias = "nature")]
+    Nature,
+    #[serde(alias = "perception")]
+    Perception,
+    #[serde(alias = "performance")]
+    Performance,
+    #[serde(alias = "persuasion")]
+    Persuasion,
+    #[serde(alias = "religion")]
+    Religion,
+    #[serde(alias = "sleight_of_hand")]
+    SleightOfHand,
+    #[serde(alias = "stealth")]
+    Stealth,
+    #[serde(alias = "survival")]
+    Survival,
+}
+
+impl Skill {
+    pub const ALL: [Skill; 18] = [
+        Skill::Acrobatics,
+        Skill::AnimalHandling,
+        Skill::Arcana,
+        Skill::Athletics,
+        Skill::Deception,
+        Skill::History,
+        Skill::Insight,
+        Skill::Intimidation,
+        Skill::Investigation,
+        Skill::Medicine,
+        Skill::Nature,
+        Skill::Perception,
+        Skill::Performance,
+        Skill::Persuasion,
+        Skill::Religion,
+        Skill::SleightOfHand,
+        Skill::Stealth,
+        Skill::Survival,
+    ];
+
+    /// The ability the skill check uses
+    pub fn ability(&self) -> Ability {
+        match self {
+            Skill::Athletics => Ability::Str,
+            Skill::Acrobatics | Skill::SleightOfHand | Skill::Stealth => Ability::Dex,
+            Skill::Arcana
+            | Skill::History
+            | Skill::Investigation
+            | Skill::Nature
+            | Skill::Religion => Ability::Int,
+            Skill::AnimalHandling
+            | Skill::Insight
+            | Skill::Medicine
+            | Skill::Perception
+            | Skill::Survival => Ability::Wis,
+            Skill::Deception | Skill::Intimidation | Skill::Performance | Skill::Persuasion => {
+                Ability::Cha
+            }
+        }
+    }
+}
+
+impl fmt::Display for Skill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Skill::AnimalHandling => write!(f, "Animal Handling"),
+            Skill::SleightOfHand => write!(f, "Sleight of Hand"),
+            skill => write!(f, "{skill:?}"),
+        }
+    }
+}
+
+/// Accepts `Sleight of Hand`, `sleight_of_hand` and `sleight-of-hand`
+impl FromStr for Skill {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = |name: &str| name.to_lowercase().replace([' ', '_', '-'], "");
+        Skill::ALL
+            .into_iter()
+            .find(|skill| normalized(&skill.to_string()) == normalized(s.trim()))
+            .ok_or_else(|| format!("unknown skill '{s}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ability_modifier() {
+        assert_eq!(ability_modifier(1), -5);
+        assert_eq!(ability_modifier(8), -1);
+        assert_eq!(ability_modifier(9), -1);
+        assert_eq!(ability_modifier(10), 0);
+        assert_eq!(ability_modifier(15), 2);
+        assert_eq!(ability_modifier(30), 10);
+    }
+
+    #[test]
+    fn test_skills() {
+        assert_eq!("sleight_of_hand".parse(), Ok(Skill::SleightOfHand));
+        assert_eq!("Animal Handling".parse(), Ok(Skill::AnimalHandling));
+        assert_eq!(" stealth".parse(), Ok(Skill::Stealth));
+        assert!("cooking".parse::<Skill>().is_err());
+        assert_eq!(Skill::Perception.ability(), Ability::Wis);
+        let skills: std::collections::HashMap<Skill, i32> =
+            serde_yml::from_str("{stealth: 6, sleight_of_hand: 4, Perception: 2}").unwrap();
+        assert_eq!(skills.len(), 3);
+    }
+}
@@ -1,7 +1,18 @@
+use std::{collections::VecDeque, fmt, str::FromStr};
+
 use crate::combat::{
-    dice::roll_dice,
-    entity::{Entity, EntityType},
+    bestiary::{Bestiary, LoadError},
+    command::Command,
+    damage::{split_damage_type, DamageModifier, DamageType},
+    dice::{roll_dice, DiceError, DiceExpr, RollResult},
+    entity::{
+        Condition, ConditionDuration, DeathSaveOutcome, DeathSaves, Effect, Entity, EntityType,
+        Trigger, TurnPhase,
+    },
+    log::{CombatEvent, LogEntry},
+    stats::{concentration_dc, Ability},
 };
+use itertools::Itertools;
 use log::{debug, info};
 use rand::{rngs::StdRng, SeedableRng};
 use serde::{Deserialize, Serialize};
@@ -13,8 +24,15 @@ struct CombatInfo {
     current_turn: usize,
     #[serde(default)]
     round: usize,
+    #[serde(default)]
+    skip_policy: SkipPolicy,
+    /// [`Entity::initiative_order`] of the lair step, which isn't saved with the entities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lair_order: Option<u32>,
     players: Vec<Entity>,
     monsters: Vec<MonsterEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    log: Vec<LogEntry>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -23,12 +41,186 @@ struct MonsterEntry {
     stats: Entity,
 }
 
+/// Which entities are skipped when advancing the turn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipPolicy {
+    /// Skip everyone at 0 HP
+    #[serde(alias = "defeated")]
+    Defeated,
+    /// Skip monsters and NPCs at 0 HP, players keep their turn to roll death saves
+    #[default]
+    #[serde(alias = "monsters")]
+    Monsters,
+    /// Don't skip anyone
+    #[serde(alias = "none")]
+    None,
+}
+
+impl SkipPolicy {
+    pub fn skips(&self, entity: &Entity) -> bool {
+        if entity.entity_type == EntityType::Lair {
+            return false;
+        }
+        match self {
+            SkipPolicy::Defeated => entity.current_hp == 0,
+            SkipPolicy::Monsters => {
+                entity.current_hp == 0 && entity.entity_type != EntityType::Player
+            }
+            SkipPolicy::None => false,
+        }
+    }
+}
+
+impl fmt::Display for SkipPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl FromStr for SkipPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "defeated" => Ok(SkipPolicy::Defeated),
+            "monsters" => Ok(SkipPolicy::Monsters),
+            "none" => Ok(SkipPolicy::None),
+            _ => Err(format!(
+                "unknown skip policy '{s}', expected defeated, monsters or none"
+            )),
+        }
+    }
+}
+
+/// Lair actions happen on initiative count 20, losing ties
+pub const LAIR_INITIATIVE: i32 = 20;
+
+/// Maximum number of commands that can be undone
+const MAX_HISTORY: usize = 200;
+
+/// The parts of the tracker that are restored by undo/redo
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    entities: Vec<Entity>,
+    current_turn: usize,
+    round: usize,
+}
+
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    command: Command,
+    before: Snapshot,
+    after: Snapshot,
+}
+
+/// A Constitution save the DM needs to resolve after a concentrating entity took damage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcentrationCheck {
+    pub target: Uuid,
+    pub spell: String,
+    pub dc: i32,
+}
+
+/// The save of one target of [`CombatTracker::group_save`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveResult {
+    pub target: Uuid,
+    pub roll: i32,
+    pub success: bool,
+    /// The damage before resistances, half of it on a success
+    pub damage: i32,
+}
+
+/// The damage an entity takes, see [`CombatTracker::damage_outcome`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DamageOutcome {
+    /// The damage after resistances, vulnerabilities and immunities
+    pub amount: i32,
+    pub damage_type: Option<DamageType>,
+    /// The amount before resistances etc. were applied, if it changed
+    pub adjusted_from: Option<i32>,
+    pub modifiers: Vec<DamageModifier>,
+    pub temp_hp_absorbed: i32,
+}
+
+impl fmt::Display for DamageOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.amount)?;
+        if let Some(damage_type) = self.damage_type {
+            write!(f, " {damage_type}")?;
+        }
+        write!(f, " damage")?;
+        if !self.modifiers.is_empty() {
+            write!(f, " [{}", self.modifiers.iter().join(", "))?;
+            if let Some(original) = self.adjusted_from {
+                write!(f, " from {original}")?;
+            }
+            write!(f, "]")?;
+        }
+        if self.temp_hp_absorbed > 0 {
+            write!(f, ", {} absorbed by temp HP", self.temp_hp_absorbed)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of [`CombatTracker::attack`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttackResult {
+    /// The natural d20 roll
+    pub roll: i32,
+    pub total: i32,
+    pub ac: i32,
+    pub hit: bool,
+    pub critical: bool,
+    /// The rolled damage of a hit, before resistances
+    pub damage: Option<RollResult>,
+    /// The damage the target took after its resistances etc.
+    pub damage_taken: Option<DamageOutcome>,
+}
+
+/// The outcome of [`CombatTracker::apply_trigger`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerResult {
+    pub roll: RollResult,
+    /// False if the heal or damage didn't change anything, e.g. a heal at full hit points.
+    /// Nothing was recorded to undo then.
+    pub applied: bool,
+}
+
+/// A trigger of an entity or one of its effects that fired and needs to be shown to the DM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTrigger {
+    pub target: Uuid,
+    /// The effect the trigger belongs to
+    pub effect: Option<String>,
+    pub trigger: Trigger,
+}
+
+impl fmt::Display for PendingTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(effect) = &self.effect {
+            write!(f, "{effect}, ")?;
+        }
+        write!(f, "{}", self.trigger)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CombatTracker {
     pub entities: Vec<Entity>,
     pub current_turn: usize,
     pub round: usize,
+    pub skip_policy: SkipPolicy,
+    /// Append-only history of what happened, not affected by undo/redo
+    pub log: Vec<LogEntry>,
     rng: StdRng,
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    /// Concentration saves caused by damage that haven't been resolved yet
+    pending_concentration_checks: VecDeque<ConcentrationCheck>,
+    /// Start and end of turn triggers that haven't been shown yet
+    pending_triggers: VecDeque<PendingTrigger>,
 }
 
 impl Default for CombatTracker {
@@ -43,14 +235,698 @@ impl CombatTracker {
             entities: Vec::new(),
             current_turn: 0,
             round: 0,
+            skip_policy: SkipPolicy::default(),
+            log: Vec::new(),
             rng: StdRng::from_rng(&mut rand::rng()),
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            pending_concentration_checks: VecDeque::new(),
+            pending_triggers: VecDeque::new(),
+        }
+    }
+
+    /// Applies `command` and records it so it can be undone.
+    /// Commands that don't change anything are not recorded, returns whether it was recorded.
+    pub fn execute(&mut self, command: Command) -> bool {
+        debug!("Executing {:?}", &command);
+        let before = self.snapshot();
+        self.apply(&command);
+        let after = self.snapshot();
+        if before == after {
+            return false;
+        }
+        self.redo_stack.clear();
+        self.undo_stack.push_back(HistoryEntry {
+            command,
+            before,
+            after,
+        });
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.pop_front();
+        }
+        true
+    }
+
+    /// Reverts the last executed command. Returns it if there was one.
+    pub fn undo(&mut self) -> Option<Command> {
+        let entry = self.undo_stack.pop_back()?;
+        info!("Undoing {:?}", &entry.command);
+        let description = self.describe(&entry.command);
+        self.restore(entry.before.clone());
+        self.log_event(None, CombatEvent::Note(format!("Undo: {description}")));
+        let command = entry.command.clone();
+        self.redo_stack.push(entry);
+        Some(command)
+    }
+
+    /// Re-applies the last undone command. Returns it if there was one.
+    pub fn redo(&mut self) -> Option<Command> {
+        let entry = self.redo_stack.pop()?;
+        info!("Redoing {:?}", &entry.command);
+        self.restore(entry.after.clone());
+        let description = self.describe(&entry.command);
+        self.log_event(None, CombatEvent::Note(format!("Redo: {description}")));
+        let command = entry.command.clone();
+        self.undo_stack.push_back(entry);
+        Some(command)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    fn apply(&mut self, command: &Command) {
+        match command {
+            Command::Damage {
+                target,
+                amount,
+                damage_type,
+                critical,
+            } => self.damage(*target, *amount, *damage_type, *critical),
+            Command::Heal { target, amount } => self.heal(*target, *amount),
+            Command::SetTempHp { target, amount } => self.set_temp_hp(*target, *amount),
+            Command::SetInitiative { target, initiative } => {
+                self.set_initiative(*target, *initiative)
+            }
+            Command::ToggleCondition {
+                target,
+                condition,
+                duration,
+            } => self.toggle_condition(*target, condition, duration.clone()),
+            Command::AddEffect { target, effect } => self.add_effect(*target, effect.clone()),
+            Command::RemoveEffect { target, name } => self.remove_effect(*target, name),
+            Command::StartConcentration { target, spell } => {
+                self.start_concentration(*target, spell)
+            }
+            Command::EndConcentration { target } => self.end_concentration(*target),
+            Command::DeathSave { target, roll } => self.death_save(*target, *roll),
+            Command::Stabilize { target } => self.stabilize(*target),
+            Command::Kill { target } => self.kill(*target),
+            Command::DelayTurn => self.delay_turn(),
+            Command::ResumeTurn { target, after } => self.resume_turn(*target, *after),
+            Command::Ready { target, action } => self.ready(*target, action),
+            Command::UseReadied { target } => self.use_readied(*target),
+            Command::UseLegendaryAction { target, cost } => {
+                self.use_legendary_action(*target, *cost)
+            }
+            Command::UseAbility { target, name } => self.use_ability(*target, name),
+            Command::RestoreAbility { target, name } => self.restore_ability(*target, name),
+            Command::AddTrigger { target, trigger } => self.add_trigger(*target, trigger.clone()),
+            Command::RemoveTrigger { target, index } => self.remove_trigger(*target, *index),
+            Command::RollInitiative {
+                group_by_name,
+                re_roll,
+            } => self.roll_initiative(*group_by_name, *re_roll),
+            Command::AddEntities(entities) => self.add_entities(entities.clone()),
+            Command::RemoveEntity { target } => self.remove_entity_by_uuid(*target),
+            Command::NextTurn => self.next_turn(),
+            Command::PrevTurn => self.prev_turn(),
+            Command::ResetCombat => self.reset_combat(),
+            Command::Batch(commands) => commands.iter().for_each(|c| self.apply(c)),
+        }
+    }
+
+    /// A short human readable description of `command`
+    fn describe(&self, command: &Command) -> String {
+        let name = |uuid: &Uuid| {
+            self.get_entity_by_uuid(*uuid)
+                .map(|e| e.display_name())
+                .unwrap_or_else(|| "unknown".to_string())
+        };
+        match command {
+            Command::Damage {
+                target,
+                amount,
+                damage_type,
+                ..
+            } => match damage_type {
+                Some(damage_type) => {
+                    format!("{amount} {damage_type} damage to {}", name(target))
+                }
+                None => format!("{amount} damage to {}", name(target)),
+            },
+            Command::Heal { target, amount } => format!("heal {amount} on {}", name(target)),
+            Command::SetTempHp { target, amount } => {
+                format!("{amount} temp HP for {}", name(target))
+            }
+            Command::SetInitiative { target, initiative } => {
+                format!("initiative {initiative} for {}", name(target))
+            }
+            Command::ToggleCondition {
+                target, condition, ..
+            } => {
+                format!("toggle {condition} on {}", name(target))
+            }
+            Command::AddEffect { target, effect } => format!("{effect} on {}", name(target)),
+            Command::RemoveEffect {
+                target,
+                name: effect,
+            } => {
+                format!("remove {effect} from {}", name(target))
+            }
+            Command::StartConcentration { target, spell } => {
+                format!("{} concentrates on {spell}", name(target))
+            }
+            Command::EndConcentration { target } => {
+                format!("end concentration of {}", name(target))
+            }
+            Command::DeathSave { target, roll } => {
+                format!("death save {roll} for {}", name(target))
+            }
+            Command::Stabilize { target } => format!("stabilize {}", name(target)),
+            Command::Kill { target } => format!("kill {}", name(target)),
+            Command::DelayTurn => "delay turn".to_string(),
+            Command::ResumeTurn { target, after } => {
+                format!("{} acts after {}", name(target), name(after))
+            }
+            Command::Ready { target, action } => format!("{} readies {action}", name(target)),
+            Command::UseReadied { target } => format!("{} uses readied action", name(target)),
+            Command::UseLegendaryAction { target, cost } => {
+                format!("{} uses legendary action ({cost})", name(target))
+            }
+            Command::UseAbility {
+                target,
+                name: ability,
+            } => format!("{} uses {ability}", name(target)),
+            Command::RestoreAbility {
+                target,
+                name: ability,
+            } => format!("restore {ability} of {}", name(target)),
+            Command::AddTrigger { target, trigger } => {
+                format!("{trigger} for {}", name(target))
+            }
+            Command::RemoveTrigger { target, index } => {
+                format!("remove trigger {} from {}", index + 1, name(target))
+            }
+            Command::RollInitiative { .. } => "roll initiative".to_string(),
+            Command::AddEntities(entities) => {
+                let names = entities.iter().map(|e| &e.name).unique().join(", ");
+                format!("add {} ({names})", entities.len())
+            }
+            Command::RemoveEntity { target } => format!("remove {}", name(target)),
+            Command::NextTurn => "next turn".to_string(),
+            Command::PrevTurn => "previous turn".to_string(),
+            Command::ResetCombat => "reset combat".to_string(),
+            Command::Batch(commands) => commands.iter().map(|c| self.describe(c)).join(", "),
+        }
+    }
+
+    /// Appends an event to the combat log, the actor is the entity whose turn it is
+    fn log_event(&mut self, target: Option<String>, event: CombatEvent) {
+        let entry = LogEntry {
+            round: self.round,
+            turn: self.current_turn,
+            actor: self
+                .entities
+                .get(self.current_turn)
+                .map(|e| e.display_name()),
+            target,
+            event,
+        };
+        info!("{}", &entry);
+        self.log.push(entry);
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            entities: self.entities.clone(),
+            current_turn: self.current_turn,
+            round: self.round,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.entities = snapshot.entities;
+        self.current_turn = snapshot.current_turn;
+        self.round = snapshot.round;
+    }
+
+    pub fn get_entity_by_uuid(&self, uuid: Uuid) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.uuid == uuid)
+    }
+
+    pub fn get_entity_by_uuid_mut(&mut self, uuid: Uuid) -> Option<&mut Entity> {
+        self.entities.iter_mut().find(|e| e.uuid == uuid)
+    }
+
+    /// Like [`Self::get_entity_by_uuid`], but not the lair step, which can't be targeted
+    fn get_combatant(&self, uuid: Uuid) -> Option<&Entity> {
+        self.get_entity_by_uuid(uuid)
+            .filter(|e| e.entity_type != EntityType::Lair)
+    }
+
+    fn get_combatant_mut(&mut self, uuid: Uuid) -> Option<&mut Entity> {
+        self.get_entity_by_uuid_mut(uuid)
+            .filter(|e| e.entity_type != EntityType::Lair)
+    }
+
+    /// Damages the target, taking its resistances, vulnerabilities and immunities into account.
+    /// Damage of a `critical` hit to a dying player counts as two failed death saves.
+    pub fn damage(
+        &mut self,
+        target: Uuid,
+        amount: i32,
+        damage_type: Option<DamageType>,
+        critical: bool,
+    ) {
+        let Some(DamageOutcome {
+            amount: adjusted,
+            adjusted_from,
+            modifiers,
+            ..
+        }) = self.damage_outcome(target, amount, damage_type)
+        else {
+            return;
+        };
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            let hp_before = entity.current_hp;
+            let temp_hp_absorbed = entity.take_damage(adjusted);
+            // damage to a player at 0 hit points counts as a failed death save, two on a critical hit
+            let death_save = (hp_before == 0
+                && adjusted > temp_hp_absorbed
+                && entity.entity_type == EntityType::Player
+                && !entity.is_dead())
+            .then(|| {
+                let outcome = entity.fail_death_save(if critical { 2 } else { 1 });
+                CombatEvent::DeathSave {
+                    roll: None,
+                    outcome,
+                    successes: entity.death_saves.successes,
+                    failures: entity.death_saves.failures,
+                }
+            });
+            let event = CombatEvent::Damage {
+                amount: adjusted,
+                damage_type,
+                adjusted_from,
+                modifiers,
+                hp_before,
+                hp_after: entity.current_hp,
+                temp_hp_absorbed,
+            };
+            let name = entity.display_name();
+            let concentration = entity.concentration.clone();
+            let dropped = entity.current_hp == 0;
+            self.log_event(Some(name.clone()), event);
+            if let Some(death_save) = death_save {
+                self.log_event(Some(name), death_save);
+            }
+
+            if let Some(spell) = concentration {
+                if dropped {
+                    self.end_concentration(target);
+                } else if adjusted > 0 {
+                    self.pending_concentration_checks
+                        .push_back(ConcentrationCheck {
+                            target,
+                            spell,
+                            dc: concentration_dc(adjusted),
+                        });
+                }
+            }
+        }
+    }
+
+    /// The damage `target` would take from `amount`, after its resistances, vulnerabilities
+    /// and immunities, and how much of it its temporary hit points would absorb
+    pub fn damage_outcome(
+        &self,
+        target: Uuid,
+        amount: i32,
+        damage_type: Option<DamageType>,
+    ) -> Option<DamageOutcome> {
+        let entity = self.get_combatant(target)?;
+        let (adjusted, modifiers) = entity.adjust_damage(amount, damage_type);
+        Some(DamageOutcome {
+            amount: adjusted,
+            damage_type,
+            adjusted_from: (adjusted != amount).then_some(amount),
+            modifiers,
+            temp_hp_absorbed: entity.temp_hp_absorbs(adjusted),
+        })
+    }
+
+    /// Returns the next concentration save that needs to be resolved
+    pub fn next_concentration_check(&mut self) -> Option<ConcentrationCheck> {
+        self.pending_concentration_checks.pop_front()
+    }
+
+    /// Rolls a saving throw with the entity's stored modifier (0 if unknown).
+    /// `roll` can be given instead if the player rolled it.
+    pub fn saving_throw(
+        &mut self,
+        target: Uuid,
+        ability: Ability,
+        dc: i32,
+        roll: Option<i32>,
+    ) -> Option<(i32, bool)> {
+        let modifier = self.get_entity_by_uuid(target)?.save_modifier(ability);
+        let roll = roll.unwrap_or_else(|| roll_dice(&mut self.rng, 20, modifier.unwrap_or(0)));
+        let success = roll >= dc;
+        let name = self.get_entity_by_uuid(target)?.display_name();
+        self.log_event(
+            Some(name),
+            CombatEvent::SavingThrow {
+                ability,
+                roll,
+                dc,
+                success,
+            },
+        );
+        Some((roll, success))
+    }
+
+    /// Rolls a save for every target with its stored modifier, e.g. against a Fireball.
+    /// Targets that fail take `damage`, the others half of it, applied as one command.
+    /// `uses` is executed in the same command, e.g. using up the breath weapon that forced the save.
+    pub fn group_save(
+        &mut self,
+        targets: &[Uuid],
+        ability: Ability,
+        dc: i32,
+        damage: i32,
+        damage_type: Option<DamageType>,
+        uses: Option<Command>,
+    ) -> Vec<SaveResult> {
+        let results: Vec<SaveResult> = targets
+            .iter()
+            .filter_map(|&target| {
+                let (roll, success) = self.saving_throw(target, ability, dc, None)?;
+                Some(SaveResult {
+                    target,
+                    roll,
+                    success,
+                    damage: if success { damage / 2 } else { damage },
+                })
+            })
+            .collect();
+        let damage = results
+            .iter()
+            .filter(|result| result.damage > 0)
+            .map(|result| Command::Damage {
+                target: result.target,
+                amount: result.damage,
+                damage_type,
+                critical: false,
+            });
+        let commands: Vec<Command> = uses.into_iter().chain(damage).collect();
+        if let Some(command) = Command::batch(commands) {
+            self.execute(command);
+        }
+        results
+    }
+
+    /// Rolls an attack of `attacker` against the AC of `target`. A natural 20 always hits
+    /// and doubles the damage dice, a natural 1 always misses.
+    /// The damage of a hit is applied as its own command, so it can be undone,
+    /// together with `uses`, e.g. using up the ability the attack was made with.
+    /// `roll` is the natural d20 roll if it was rolled at the table.
+    pub fn attack(
+        &mut self,
+        attacker: Uuid,
+        target: Uuid,
+        bonus: i32,
+        damage: Option<(&DiceExpr, Option<DamageType>)>,
+        roll: Option<i32>,
+        uses: Option<Command>,
+    ) -> Result<Option<AttackResult>, DiceError> {
+        let Some(attacker) = self.get_entity_by_uuid(attacker).map(Entity::display_name) else {
+            return Ok(None);
+        };
+        let Some(entity) = self.get_entity_by_uuid(target) else {
+            return Ok(None);
+        };
+        let (name, ac) = (entity.display_name(), entity.ac);
+        let roll = roll.unwrap_or_else(|| roll_dice(&mut self.rng, 20, 0));
+        let critical = roll == 20;
+        let hit = critical || (roll != 1 && roll.saturating_add(bonus) >= ac);
+        // roll the damage first, so an invalid roll doesn't log a half finished attack
+        let damage = match damage {
+            Some((expr, damage_type)) if hit => {
+                let result = if critical {
+                    self.roll(&expr.critical()?)?
+                } else {
+                    self.roll(expr)?
+                };
+                Some((result, damage_type))
+            }
+            _ => None,
+        };
+        let damage_taken = damage.as_ref().and_then(|(result, damage_type)| {
+            self.damage_outcome(target, result.total.max(0), *damage_type)
+        });
+        self.log_event(
+            Some(name),
+            CombatEvent::Attack {
+                attacker,
+                roll,
+                bonus,
+                ac,
+                hit,
+            },
+        );
+        let commands =
+            uses.into_iter().chain(
+                damage
+                    .as_ref()
+                    .map(|(result, damage_type)| Command::Damage {
+                        target,
+                        amount: result.total.max(0),
+                        damage_type: *damage_type,
+                        critical,
+                    }),
+            );
+        if let Some(command) = Command::batch(commands.collect()) {
+            self.execute(command);
+        }
+        let damage = damage.map(|(result, _)| result);
+        Ok(Some(AttackResult {
+            roll,
+            total: roll.saturating_add(bonus),
+            ac,
+            hit,
+            critical,
+            damage,
+            damage_taken,
+        }))
+    }
+
+    pub fn start_concentration(&mut self, target: Uuid, spell: &str) {
+        // concentrating on a new spell ends the previous one
+        self.end_concentration(target);
+        if let Some(entity) = self.get_combatant_mut(target) {
+            entity.concentration = Some(spell.to_string());
+            let name = entity.display_name();
+            self.log_event(
+                Some(name),
+                CombatEvent::ConcentrationStarted(spell.to_string()),
+            );
+        }
+    }
+
+    /// Ends the concentration of `target` and removes the effects tied to the spell
+    pub fn end_concentration(&mut self, target: Uuid) {
+        let Some(entity) = self.get_entity_by_uuid_mut(target) else {
+            return;
+        };
+        let Some(spell) = entity.concentration.take() else {
+            return;
+        };
+        let name = entity.display_name();
+        self.log_event(Some(name), CombatEvent::ConcentrationEnded(spell.clone()));
+
+        let mut removed = Vec::new();
+        for entity in self.entities.iter_mut() {
+            let name = entity.display_name();
+            entity.effects.retain(|effect| {
+                let tied =
+                    effect.source == Some(target) && effect.name.eq_ignore_ascii_case(&spell);
+                if tied {
+                    removed.push((name.clone(), effect.to_string()));
+                }
+                !tied
+            });
+        }
+        for (name, effect) in removed {
+            self.log_event(Some(name), CombatEvent::EffectRemoved(effect));
+        }
+    }
+
+    /// Resolves a death save of a dying player with the natural d20 `roll`
+    pub fn death_save(&mut self, target: Uuid, roll: i32) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if !entity.is_dying() {
+                return;
+            }
+            let outcome = entity.death_save(roll);
+            let event = CombatEvent::DeathSave {
+                roll: Some(roll),
+                outcome,
+                successes: entity.death_saves.successes,
+                failures: entity.death_saves.failures,
+            };
+            let name = entity.display_name();
+            self.log_event(Some(name), event);
+        }
+    }
+
+    /// Stabilizes a dying player, e.g. with a Medicine check or Spare the Dying
+    pub fn stabilize(&mut self, target: Uuid) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if !entity.is_dying() {
+                return;
+            }
+            entity.stabilize();
+            let name = entity.display_name();
+            self.log_event(
+                Some(name),
+                CombatEvent::DeathSave {
+                    roll: None,
+                    outcome: DeathSaveOutcome::Stabilized,
+                    successes: 0,
+                    failures: 0,
+                },
+            );
+        }
+    }
+
+    /// Takes the target out of the fight without going through damage and death saves
+    pub fn kill(&mut self, target: Uuid) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if entity.entity_type == EntityType::Lair || entity.is_dead() {
+                return;
+            }
+            entity.kill();
+            let name = entity.display_name();
+            self.log_event(Some(name), CombatEvent::Killed);
+            self.end_concentration(target);
+        }
+    }
+
+    pub fn heal(&mut self, target: Uuid, amount: i32) {
+        if let Some(entity) = self.get_combatant_mut(target) {
+            // a dead character can't be healed back, only revived
+            if entity.entity_type == EntityType::Player && entity.is_dead() {
+                return;
+            }
+            let hp_before = entity.current_hp;
+            entity.heal(amount);
+            if entity.current_hp == hp_before {
+                return;
+            }
+            let event = CombatEvent::Heal {
+                amount,
+                hp_before,
+                hp_after: entity.current_hp,
+            };
+            let name = entity.display_name();
+            self.log_event(Some(name), event);
         }
     }
 
+    pub fn set_temp_hp(&mut self, target: Uuid, amount: i32) {
+        if let Some(entity) = self.get_combatant_mut(target) {
+            let temp_hp_before = entity.temp_hp;
+            entity.set_temp_hp(amount);
+            if entity.temp_hp == temp_hp_before {
+                return;
+            }
+            let event = CombatEvent::TempHp {
+                amount,
+                temp_hp_before,
+                temp_hp_after: entity.temp_hp,
+            };
+            let name = entity.display_name();
+            self.log_event(Some(name), event);
+        }
+    }
+
+    pub fn set_initiative(&mut self, target: Uuid, initiative: i32) {
+        if let Some(entity) = self.get_combatant_mut(target) {
+            info!(
+                "Setting initiative of entity {} ({}) to {}",
+                entity.name, entity.id, &initiative
+            );
+            entity.initiative = Some(initiative);
+            entity.initiative_order = 0;
+            self.sort_by_initiative();
+        }
+    }
+
+    pub fn toggle_condition(
+        &mut self,
+        target: Uuid,
+        condition: &Condition,
+        duration: Option<ConditionDuration>,
+    ) {
+        // a condition applied during the source's turn lasts until the end of its next turn
+        let current_uuid = self.current_uuid();
+        let duration = match duration {
+            Some(ConditionDuration::EndOfSourceTurn { source, .. }) => {
+                Some(ConditionDuration::EndOfSourceTurn {
+                    source,
+                    skip_current: Some(source) == current_uuid,
+                })
+            }
+            duration => duration,
+        };
+        if let Some(entity) = self.get_combatant_mut(target) {
+            let event = if entity.conditions.contains(condition) {
+                entity.remove_condition(condition);
+                CombatEvent::ConditionRemoved(condition.clone())
+            } else {
+                entity.add_condition_with_duration(condition.clone(), duration);
+                CombatEvent::ConditionAdded(condition.clone())
+            };
+            let name = entity.display_name();
+            self.log_event(Some(name), event);
+        }
+    }
+
+    /// Adds a custom effect, an `EndOfSourceTurn` duration is handled like for conditions
+    pub fn add_effect(&mut self, target: Uuid, mut effect: Effect) {
+        let current_uuid = self.current_uuid();
+        if let Some(ConditionDuration::EndOfSourceTurn {
+            source,
+            skip_current,
+        }) = &mut effect.duration
+        {
+            *skip_current = Some(*source) == current_uuid;
+        }
+        if let Some(entity) = self.get_combatant_mut(target) {
+            let event = CombatEvent::EffectAdded(effect.to_string());
+            entity.add_effect(effect);
+            let name = entity.display_name();
+            self.log_event(Some(name), event);
+        }
+    }
+
+    pub fn remove_effect(&mut self, target: Uuid, effect_name: &str) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if let Some(effect) = entity.remove_effect(effect_name) {
+                let name = entity.display_name();
+                self.log_event(Some(name), CombatEvent::EffectRemoved(effect.to_string()));
+            }
+        }
+    }
+
+    /// Rolls the given dice expression with the tracker's RNG
+    pub fn roll(&mut self, expr: &DiceExpr) -> Result<RollResult, DiceError> {
+        expr.roll(&mut self.rng)
+    }
+
     pub fn roll_initiative(&mut self, group_by_name: bool, re_roll: bool) {
         info!("Re-rolling initiative");
         let mut initiative_map = std::collections::HashMap::new();
         self.entities.iter_mut().for_each(|entity| {
+            // the lair always acts on initiative count 20
+            if entity.entity_type == EntityType::Lair {
+                return;
+            }
             if entity.initiative.is_none() || re_roll {
                 let rolled_initiative = if group_by_name {
                     *initiative_map
@@ -60,6 +936,7 @@ impl CombatTracker {
                     roll_dice(&mut self.rng, 20, entity.initiative_modifier)
                 };
                 entity.initiative = Some(rolled_initiative);
+                entity.initiative_order = 0;
             }
         });
         self.sort_by_initiative();
@@ -69,8 +946,20 @@ impl CombatTracker {
         info!("Resetting combat");
         for entity in self.entities.iter_mut() {
             entity.current_hp = entity.max_hp;
+            entity.temp_hp = 0;
             entity.conditions.clear();
+            entity.condition_durations.clear();
+            entity.effects.clear();
+            entity.concentration = None;
+            entity.death_saves = DeathSaves::default();
+            entity.delaying = false;
+            entity.readied = None;
+            entity.legendary_actions_used = 0;
+            for ability in entity.limited_abilities.iter_mut() {
+                ability.used = 0;
+            }
         }
+        self.pending_triggers.clear();
         self.round = 0;
         self.current_turn = 0;
     }
@@ -93,98 +982,668 @@ impl CombatTracker {
         self.entities.push(new_entity);
     }
 
-    pub fn remove_entity_by_uuid(&mut self, entity_id: Uuid) {
-        self.entities.retain(|entity| entity.uuid != entity_id);
+    /// Adds entities to a running combat and sorts them into the initiative order.
+    /// Missing initiatives are rolled, once for all new entities with the same name.
+    pub fn add_entities(&mut self, entities: Vec<Entity>) {
+        let mut initiative_map = std::collections::HashMap::new();
+        let mut joined = Vec::new();
+        for mut entity in entities {
+            let initiative = match entity.initiative {
+                Some(initiative) => initiative,
+                None => *initiative_map
+                    .entry(entity.name.clone())
+                    .or_insert_with(|| roll_dice(&mut self.rng, 20, entity.initiative_modifier)),
+            };
+            entity.initiative = Some(initiative);
+            joined.push((entity.uuid, initiative));
+            self.add_entity(entity);
+        }
+        self.update_lair();
+        self.sort_by_initiative();
+        for (uuid, initiative) in joined {
+            let name = self.get_entity_by_uuid(uuid).map(|e| e.display_name());
+            self.log_event(name, CombatEvent::Joined { initiative });
+        }
+    }
 
-        // Ensure we don't go out of bounds in case the last entity was removed
-        if self.current_turn >= self.entities.len() {
-            self.current_turn = 0;
+    /// Removes an entity from the combat, the current turn stays with the same entity.
+    /// If the entity whose turn it is gets removed, the turn passes on to the next one.
+    pub fn remove_entity_by_uuid(&mut self, entity_id: Uuid) {
+        // the lair step comes and goes with the lair actions
+        if self.get_combatant(entity_id).is_some() {
+            self.remove_entity(entity_id);
         }
     }
 
-    pub fn next_turn(&mut self) {
-        if !self.entities.is_empty() {
-            self.current_turn = (self.current_turn + 1) % self.entities.len();
+    fn remove_entity(&mut self, entity_id: Uuid) {
+        let Some(index) = self.entities.iter().position(|e| e.uuid == entity_id) else {
+            return;
+        };
+        if index == self.current_turn {
+            // its turn ends as if it passed, its reminders are outdated
+            self.end_turn();
+            self.pending_triggers.clear();
         }
-        if self.current_turn == 0 {
-            self.round += 1;
+        // effects of a spell it concentrated on end with it
+        self.end_concentration(entity_id);
+        let entity = self.entities.remove(index);
+        // the lair step isn't a combatant, it goes away with the last lair action
+        if entity.entity_type != EntityType::Lair {
+            self.log_event(Some(entity.display_name()), CombatEvent::Left);
         }
-    }
+        // durations waiting for the end of its turn would never run out
+        self.expire_from_source(entity_id);
 
-    pub fn prev_turn(&mut self) {
-        if self.current_turn == 0 {
-            self.round = self.round.saturating_sub(1);
-            if self.round != 0 {
-                self.current_turn = self.entities.len() - 1;
+        if self.entities.is_empty() {
+            self.current_turn = 0;
+        } else if index < self.current_turn {
+            self.current_turn -= 1;
+        } else if index == self.current_turn {
+            if self.current_turn >= self.entities.len() {
+                self.current_turn = 0;
+                self.round += 1;
             }
-        } else {
-            self.current_turn = self.current_turn.saturating_sub(1);
+            for _ in 0..self.entities.len() {
+                if !self.skips_current() {
+                    break;
+                }
+                self.end_turn();
+                self.advance_turn();
+            }
+            self.start_turn();
         }
+        self.update_lair();
     }
 
-    pub fn get_current_entity(&self) -> Option<Entity> {
-        self.entities.get(self.current_turn).cloned()
+    /// Advances to the next entity that isn't skipped by the [`SkipPolicy`].
+    /// The turns of skipped entities still end, so their durations run out.
+    pub fn next_turn(&mut self) {
+        // triggers of the previous turn that weren't shown are outdated
+        self.pending_triggers.clear();
+        for _ in 0..self.entities.len().max(1) {
+            self.end_turn();
+            self.advance_turn();
+            if !self.skips_current() {
+                break;
+            }
+        }
+        self.start_turn();
     }
 
-    pub fn sort_by_initiative(&mut self) {
-        debug!("Sorting by initiative!");
-        self.entities.sort_by(|a, b| {
-            let initiative_cmp = b.initiative.cmp(&a.initiative);
-            // prefer EntityType::Player in the case of a tie
-            if initiative_cmp == std::cmp::Ordering::Equal {
-                matches!(b.entity_type, EntityType::Player)
-                    .cmp(&matches!(a.entity_type, EntityType::Player))
-            } else {
-                initiative_cmp
-            }
+    /// Takes the current entity out of the rotation until [`Self::resume_turn`].
+    /// Its turn doesn't end, so its durations keep running.
+    /// The lair step can't delay, and nobody can if no other combatant could take the turn.
+    pub fn delay_turn(&mut self) {
+        let current = self.current_turn;
+        if self
+            .entities
+            .get(current)
+            .is_none_or(|e| e.entity_type == EntityType::Lair)
+        {
+            return;
+        }
+        let can_take_over = self.entities.iter().enumerate().any(|(i, e)| {
+            i != current
+                && e.entity_type != EntityType::Lair
+                && !e.delaying
+                && !self.skip_policy.skips(e)
         });
+        if !can_take_over {
+            return;
+        }
+        self.pending_triggers.clear();
+        let Some(entity) = self.entities.get_mut(current) else {
+            return;
+        };
+        entity.delaying = true;
+        let name = entity.display_name();
+        self.log_event(Some(name), CombatEvent::Delayed);
+        for _ in 0..self.entities.len() {
+            self.advance_turn();
+            if !self.skips_current() {
+                break;
+            }
+        }
+        self.start_turn();
     }
 
-    fn get_combat_info(&self) -> CombatInfo {
-        let (players, monsters): (Vec<Entity>, Vec<Entity>) = self
+    /// Puts a delaying entity back into the order right after `after`, taking its initiative.
+    /// It will act once the turn of `after` ends.
+    pub fn resume_turn(&mut self, target: Uuid, after: Uuid) {
+        if target == after {
+            return;
+        }
+        let Some(after_entity) = self.get_entity_by_uuid(after) else {
+            return;
+        };
+        let initiative = after_entity.initiative;
+        let after_name = after_entity.display_name();
+        let Some(index) = self
             .entities
             .iter()
-            .cloned()
-            .partition(|e| matches!(e.entity_type, EntityType::Player));
-        CombatInfo {
-            current_turn: self.current_turn,
-            round: self.round,
-            players,
-            monsters: monsters
-                .iter()
-                .map(|e| MonsterEntry {
-                    count: Some(1),
-                    stats: e.clone(),
-                })
-                .collect(),
+            .position(|e| e.uuid == target && e.delaying)
+        else {
+            return;
+        };
+        let current = self.current_uuid();
+        let mut entity = self.entities.remove(index);
+        entity.delaying = false;
+        entity.initiative = initiative;
+        let name = entity.display_name();
+        let after_index = self
+            .entities
+            .iter()
+            .position(|e| e.uuid == after)
+            .expect("the entity to act after was checked above");
+        self.entities.insert(after_index + 1, entity);
+        // keep the order within the initiative count when sorting again
+        for (order, entity) in self
+            .entities
+            .iter_mut()
+            .filter(|e| e.initiative == initiative)
+            .enumerate()
+        {
+            entity.initiative_order = order as u32;
         }
+        if let Some(index) =
+            current.and_then(|uuid| self.entities.iter().position(|e| e.uuid == uuid))
+        {
+            self.current_turn = index;
+        }
+        self.log_event(Some(name), CombatEvent::Resumed { after: after_name });
     }
 
-    pub fn from_yaml(yaml_string: String) -> Self {
-        let combat_data: CombatInfo =
-            serde_yml::from_str(&yaml_string).expect("Failed to parse YAML");
-
-        let mut tracker = CombatTracker::new();
-        tracker.current_turn = combat_data.current_turn;
-        tracker.round = combat_data.round;
-        for mut player in combat_data.players {
-            player.entity_type = EntityType::Player;
-            tracker.add_entity(player);
+    pub fn ready(&mut self, target: Uuid, action: &str) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            entity.readied = Some(action.to_string());
+            let name = entity.display_name();
+            self.log_event(Some(name), CombatEvent::Readied(action.to_string()));
         }
-        for monster_entry in combat_data.monsters {
-            let count = monster_entry.count.unwrap_or(1);
-            for _ in 0..count {
-                let mut monster = monster_entry.stats.clone();
-                monster.entity_type = EntityType::Monster;
-                if monster_entry.stats.current_hp == 0 {
-                    // set current_hp to max_hp if current_hp not set
-                    monster.current_hp = monster.max_hp;
-                }
-                tracker.add_entity(monster);
+    }
+
+    pub fn use_readied(&mut self, target: Uuid) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if let Some(action) = entity.readied.take() {
+                let name = entity.display_name();
+                self.log_event(Some(name), CombatEvent::ReadiedUsed(action));
             }
         }
+    }
+
+    /// Uses the ability called `name`, does nothing if it isn't available
+    pub fn use_ability(&mut self, target: Uuid, name: &str) {
+        let Some(entity) = self.get_entity_by_uuid_mut(target) else {
+            return;
+        };
+        let Some(ability) = entity
+            .limited_abilities
+            .iter_mut()
+            .find(|a| a.name == name && a.is_available())
+        else {
+            return;
+        };
+        ability.used += 1;
+        let entity_name = entity.display_name();
+        self.log_event(
+            Some(entity_name),
+            CombatEvent::AbilityUsed(name.to_string()),
+        );
+    }
+
+    pub fn restore_ability(&mut self, target: Uuid, name: &str) {
+        let Some(entity) = self.get_entity_by_uuid_mut(target) else {
+            return;
+        };
+        let Some(ability) = entity
+            .limited_abilities
+            .iter_mut()
+            .find(|a| a.name == name && a.used > 0)
+        else {
+            return;
+        };
+        ability.used = 0;
+        let entity_name = entity.display_name();
+        self.log_event(
+            Some(entity_name),
+            CombatEvent::AbilityRestored(name.to_string()),
+        );
+    }
+
+    /// Rolls a d6 for every spent recharge ability of the current entity
+    fn roll_recharges(&mut self) {
+        let Some(entity) = self.entities.get_mut(self.current_turn) else {
+            return;
+        };
+        let name = entity.display_name();
+        let mut events = Vec::new();
+        for ability in entity
+            .limited_abilities
+            .iter_mut()
+            .filter(|a| a.needs_recharge())
+        {
+            let roll = roll_dice(&mut self.rng, 6, 0);
+            let success = ability.recharge.is_some_and(|min| roll >= min);
+            if success {
+                ability.used = 0;
+            }
+            events.push(CombatEvent::Recharge {
+                ability: ability.name.clone(),
+                roll,
+                success,
+            });
+        }
+        for event in events {
+            self.log_event(Some(name.clone()), event);
+        }
+    }
+
+    pub fn add_trigger(&mut self, target: Uuid, trigger: Trigger) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            entity.triggers.push(trigger);
+        }
+    }
+
+    pub fn remove_trigger(&mut self, target: Uuid, index: usize) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            if index < entity.triggers.len() {
+                entity.triggers.remove(index);
+            }
+        }
+    }
+
+    /// Returns the next start or end of turn trigger that needs to be shown
+    pub fn next_trigger(&mut self) -> Option<PendingTrigger> {
+        self.pending_triggers.pop_front()
+    }
+
+    /// Rolls the heal or damage of an automatic trigger and applies it as its own command,
+    /// so it can be undone. Returns `None` for plain reminders.
+    pub fn apply_trigger(
+        &mut self,
+        pending: &PendingTrigger,
+    ) -> Result<Option<TriggerResult>, DiceError> {
+        let trigger = &pending.trigger;
+        if let Some(heal) = &trigger.heal {
+            let roll = self.roll(&heal.parse()?)?;
+            let applied = self.execute(Command::Heal {
+                target: pending.target,
+                amount: roll.total.max(0),
+            });
+            Ok(Some(TriggerResult { roll, applied }))
+        } else if let Some(damage) = &trigger.damage {
+            let (dice, damage_type) = split_damage_type(damage);
+            let roll = self.roll(&dice.parse()?)?;
+            let applied = self.execute(Command::Damage {
+                target: pending.target,
+                amount: roll.total.max(0),
+                damage_type,
+                critical: false,
+            });
+            Ok(Some(TriggerResult { roll, applied }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Queues the current entity's triggers for `phase` and logs them
+    fn queue_triggers(&mut self, phase: TurnPhase) {
+        let Some(entity) = self.entities.get(self.current_turn) else {
+            return;
+        };
+        let name = entity.display_name();
+        let target = entity.uuid;
+        for (effect, trigger) in entity.triggers(phase) {
+            let pending = PendingTrigger {
+                target,
+                effect,
+                trigger,
+            };
+            self.log_event(
+                Some(name.clone()),
+                CombatEvent::Triggered(pending.to_string()),
+            );
+            self.pending_triggers.push_back(pending);
+        }
+    }
+
+    /// Spends `cost` legendary actions, does nothing if not enough are left
+    pub fn use_legendary_action(&mut self, target: Uuid, cost: u32) {
+        if let Some(entity) = self.get_entity_by_uuid_mut(target) {
+            let remaining = entity.legendary_actions_remaining();
+            if cost == 0 || cost > remaining {
+                return;
+            }
+            entity.legendary_actions_used += cost;
+            let name = entity.display_name();
+            self.log_event(
+                Some(name),
+                CombatEvent::LegendaryAction {
+                    cost,
+                    remaining: remaining - cost,
+                },
+            );
+        }
+    }
+
+    /// Adds the lair step while an entity has lair actions and removes it once none has
+    fn update_lair(&mut self) {
+        let has_lair_actions = self.entities.iter().any(|e| !e.lair_actions.is_empty());
+        let lair = self
+            .entities
+            .iter()
+            .find(|e| e.entity_type == EntityType::Lair)
+            .map(|e| e.uuid);
+        match (has_lair_actions, lair) {
+            (true, None) => {
+                let mut lair = Entity::new("Lair", EntityType::Lair, 0, 0, 0);
+                lair.initiative = Some(LAIR_INITIATIVE);
+                self.entities.push(lair);
+                self.sort_by_initiative();
+            }
+            (false, Some(lair)) => self.remove_entity(lair),
+            _ => {}
+        }
+    }
+
+    /// The lair actions of all entities, for the lair step
+    pub fn lair_actions(&self) -> Vec<(String, String)> {
+        self.entities
+            .iter()
+            .flat_map(|e| {
+                e.lair_actions
+                    .iter()
+                    .map(|action| (e.display_name(), action.clone()))
+            })
+            .collect()
+    }
+
+    /// Refreshes legendary actions, rolls recharges, a readied action that wasn't used expires
+    /// and the start of turn triggers fire
+    fn start_turn(&mut self) {
+        let Some(entity) = self.entities.get_mut(self.current_turn) else {
+            return;
+        };
+        entity.legendary_actions_used = 0;
+        if let Some(action) = entity.readied.take() {
+            let name = entity.display_name();
+            self.log_event(Some(name), CombatEvent::ReadiedExpired(action));
+        }
+        self.roll_recharges();
+        self.queue_triggers(TurnPhase::Start);
+    }
+
+    /// Moves the turn to the next entity, a new round starts after the last one
+    fn advance_turn(&mut self) {
+        if !self.entities.is_empty() {
+            self.current_turn = (self.current_turn + 1) % self.entities.len();
+        }
+        if self.current_turn == 0 {
+            self.round += 1;
+        }
+    }
+
+    fn skips_current(&self) -> bool {
+        self.entities
+            .get(self.current_turn)
+            .is_some_and(|e| e.delaying || self.skip_policy.skips(e))
+    }
+
+    /// Fires the end of turn triggers, counts down condition durations
+    /// at the end of the current entity's turn and removes the ones that expired
+    fn end_turn(&mut self) {
+        let Some(ending) = self.current_uuid() else {
+            return;
+        };
+        // skipped entities don't act, so there's nothing to remind of
+        if !self.skips_current() {
+            self.queue_triggers(TurnPhase::End);
+        }
+        let mut expired = Vec::new();
+        for entity in self.entities.iter_mut() {
+            let owner = entity.uuid;
+            let name = entity.display_name();
+            entity.condition_durations.retain(|condition, duration| {
+                let active = duration.tick(ending, owner);
+                if !active {
+                    expired.push((
+                        owner,
+                        name.clone(),
+                        CombatEvent::ConditionExpired(condition.clone()),
+                    ));
+                }
+                active
+            });
+            entity.effects.retain_mut(|effect| {
+                let active = effect
+                    .duration
+                    .as_mut()
+                    .is_none_or(|duration| duration.tick(ending, owner));
+                if !active {
+                    expired.push((
+                        owner,
+                        name.clone(),
+                        CombatEvent::EffectExpired(effect.to_string()),
+                    ));
+                }
+                active
+            });
+        }
+        self.remove_expired(expired);
+    }
+
+    /// Ends the conditions and effects that `source` applied to others
+    fn expire_from_source(&mut self, source: Uuid) {
+        let from_source = |duration: &ConditionDuration| matches!(duration, ConditionDuration::EndOfSourceTurn { source: s, .. } if *s == source);
+        let mut expired = Vec::new();
+        for entity in self.entities.iter_mut() {
+            let owner = entity.uuid;
+            let name = entity.display_name();
+            entity.condition_durations.retain(|condition, duration| {
+                if from_source(duration) {
+                    expired.push((
+                        owner,
+                        name.clone(),
+                        CombatEvent::ConditionExpired(condition.clone()),
+                    ));
+                    return false;
+                }
+                true
+            });
+            entity.effects.retain(|effect| {
+                if effect.source == Some(source)
+                    || effect.duration.as_ref().is_some_and(from_source)
+                {
+                    expired.push((
+                        owner,
+                        name.clone(),
+                        CombatEvent::EffectExpired(effect.to_string()),
+                    ));
+                    return false;
+                }
+                true
+            });
+        }
+        self.remove_expired(expired);
+    }
+
+    /// Removes the conditions of expired durations and logs the expiry
+    fn remove_expired(&mut self, expired: Vec<(Uuid, String, CombatEvent)>) {
+        for (uuid, name, event) in expired {
+            if let (Some(entity), CombatEvent::ConditionExpired(condition)) =
+                (self.get_entity_by_uuid_mut(uuid), &event)
+            {
+                entity.conditions.remove(condition);
+            }
+            self.log_event(Some(name), event);
+        }
+    }
+
+    /// Goes back to the previous entity that isn't skipped by the [`SkipPolicy`].
+    /// Going back from the first entity returns to the last entity of the previous round.
+    pub fn prev_turn(&mut self) {
+        for _ in 0..self.entities.len().max(1) {
+            if self.current_turn > 0 {
+                self.current_turn -= 1;
+            } else if self.round > 0 && !self.entities.is_empty() {
+                self.round -= 1;
+                self.current_turn = self.entities.len() - 1;
+            } else {
+                break;
+            }
+            if !self.skips_current() {
+                break;
+            }
+        }
+    }
+
+    pub fn get_current_entity(&self) -> Option<Entity> {
+        self.entities.get(self.current_turn).cloned()
+    }
+
+    /// The UUID of the entity whose turn it is
+    pub fn current_uuid(&self) -> Option<Uuid> {
+        self.entities.get(self.current_turn).map(|e| e.uuid)
+    }
+
+    /// Nobody has finished their turn yet
+    fn is_before_first_turn(&self) -> bool {
+        self.round == 0 && self.current_turn == 0
+    }
+
+    /// Sorts the entities by initiative.
+    /// The current turn stays with the same entity, except before the first turn,
+    /// where the new order decides who starts.
+    pub fn sort_by_initiative(&mut self) {
+        debug!("Sorting by initiative!");
+        let current = if self.is_before_first_turn() {
+            None
+        } else {
+            self.current_uuid()
+        };
+        self.entities.sort_by(|a, b| {
+            // an explicit order from resuming a turn comes first, then prefer
+            // EntityType::Player in the case of a tie, the lair loses ties
+            let rank = |entity: &Entity| match entity.entity_type {
+                EntityType::Player => 0,
+                EntityType::Lair => 2,
+                _ => 1,
+            };
+            b.initiative
+                .cmp(&a.initiative)
+                .then(a.initiative_order.cmp(&b.initiative_order))
+                .then_with(|| rank(a).cmp(&rank(b)))
+        });
+        if let Some(index) =
+            current.and_then(|uuid| self.entities.iter().position(|e| e.uuid == uuid))
+        {
+            self.current_turn = index;
+        }
+    }
+
+    fn get_combat_info(&self) -> CombatInfo {
+        let (players, monsters): (Vec<Entity>, Vec<Entity>) = self
+            .entities
+            .iter()
+            // the lair step is added again when loading
+            .filter(|e| e.entity_type != EntityType::Lair)
+            .cloned()
+            .partition(|e| matches!(e.entity_type, EntityType::Player));
+        let lair_order = self
+            .entities
+            .iter()
+            .find(|e| e.entity_type == EntityType::Lair)
+            .map(|e| e.initiative_order)
+            .filter(|order| *order > 0);
+        CombatInfo {
+            current_turn: self.current_turn,
+            round: self.round,
+            skip_policy: self.skip_policy,
+            lair_order,
+            players,
+            monsters: monsters
+                .iter()
+                .map(|e| MonsterEntry {
+                    count: Some(1),
+                    stats: e.clone(),
+                })
+                .collect(),
+            log: self.log.clone(),
+        }
+    }
+
+    /// Loads a combat without a bestiary, panics if it isn't valid
+    pub fn from_yaml(yaml_string: String) -> Self {
+        Self::from_yaml_with_bestiary(yaml_string, &Bestiary::new())
+            .unwrap_or_else(|error| panic!("Failed to parse YAML: {error}"))
+    }
+
+    /// Loads a combat, `monster: <name>` references are resolved with the `bestiary`
+    pub fn from_yaml_with_bestiary(
+        yaml_string: String,
+        bestiary: &Bestiary,
+    ) -> Result<Self, LoadError> {
+        let parse_error = |source| LoadError::Parse { path: None, source };
+        let mut value: serde_yml::Value = serde_yml::from_str(&yaml_string).map_err(parse_error)?;
+        bestiary.resolve(&mut value)?;
+        // entities without `current_hp` start with full hit points, an explicit 0 is kept
+        let has_current_hp = |entity: &serde_yml::Value| entity.get("current_hp").is_some();
+        let players_with_hp: Vec<bool> = value
+            .get("players")
+            .and_then(|p| p.as_sequence())
+            .map(|p| p.iter().map(has_current_hp).collect())
+            .unwrap_or_default();
+        let monsters_with_hp: Vec<bool> = value
+            .get("monsters")
+            .and_then(|m| m.as_sequence())
+            .map(|m| {
+                m.iter()
+                    .map(|entry| entry.get("stats").is_some_and(has_current_hp))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let combat_data: CombatInfo = serde_yml::from_value(value).map_err(parse_error)?;
+
+        let mut tracker = CombatTracker::new();
+        tracker.skip_policy = combat_data.skip_policy;
+        tracker.log = combat_data.log;
+        for (i, mut player) in combat_data.players.into_iter().enumerate() {
+            player.entity_type = EntityType::Player;
+            if !players_with_hp.get(i).copied().unwrap_or(false) {
+                player.current_hp = player.max_hp;
+            }
+            tracker.add_entity(player);
+        }
+        for (i, monster_entry) in combat_data.monsters.into_iter().enumerate() {
+            let count = monster_entry.count.unwrap_or(1);
+            for n in 0..count {
+                let mut monster = monster_entry.stats.clone();
+                // the copies of a stat block are separate entities
+                if n > 0 {
+                    monster.uuid = Uuid::new_v4();
+                }
+                monster.entity_type = EntityType::Monster;
+                if !monsters_with_hp.get(i).copied().unwrap_or(false) {
+                    monster.current_hp = monster.max_hp;
+                }
+                tracker.add_entity(monster);
+            }
+        }
+        // the saved current turn is an index into the initiative order
+        tracker.update_lair();
+        if let Some(order) = combat_data.lair_order {
+            for lair in tracker
+                .entities
+                .iter_mut()
+                .filter(|e| e.entity_type == EntityType::Lair)
+            {
+                lair.initiative_order = order;
+            }
+        }
+        tracker.sort_by_initiative();
+        tracker.current_turn = combat_data.current_turn;
+        tracker.round = combat_data.round;
 
-        tracker
+        Ok(tracker)
     }
 
     pub fn to_yaml(&self) -> String {
@@ -195,9 +1654,14 @@ impl CombatTracker {
 
 #[cfg(test)]
 mod tests {
+    use proptest::{prelude::*, sample::Index};
     use rand::SeedableRng;
 
     use super::*;
+    use crate::combat::damage::DamageModifier;
+    use crate::combat::entity::LimitedAbility;
+    use crate::combat::stats::{Ability, Skill};
+    use crate::dto::PlayerClientState;
 
     #[test]
     fn test_roll_initiative() {
@@ -286,6 +1750,53 @@ mod tests {
         assert_eq!(ct.entities[2].id, 3);
     }
 
+    #[test]
+    fn test_add_entities_mid_combat() {
+        let mut ct = CombatTracker::new();
+        for (name, initiative) in [("Merry", 20), ("Orc", 12), ("Pippin", 5)] {
+            let mut entity = Entity::new(name, EntityType::Player, 0, 12, 10);
+            entity.initiative = Some(initiative);
+            ct.add_entity(entity);
+        }
+        ct.next_turn();
+        assert_eq!(ct.entities[ct.current_turn].name, "Orc");
+
+        let mut goblin = Entity::new("Goblin", EntityType::Monster, 2, 13, 7);
+        goblin.initiative = Some(15);
+        let wolves = (0..2)
+            .map(|_| Entity::new("Wolf", EntityType::Monster, 2, 13, 11))
+            .collect_vec();
+        ct.execute(Command::AddEntities(
+            std::iter::once(goblin).chain(wolves).collect(),
+        ));
+
+        // the Orc keeps its turn and the new entities are sorted in
+        assert_eq!(ct.entities.len(), 6);
+        assert_eq!(ct.entities[ct.current_turn].name, "Orc");
+        assert!(ct
+            .entities
+            .windows(2)
+            .all(|w| w[0].initiative >= w[1].initiative));
+        let wolves = ct
+            .entities
+            .iter()
+            .filter(|e| e.name == "Wolf")
+            .collect_vec();
+        assert_eq!(wolves[0].initiative, wolves[1].initiative);
+        assert_eq!(wolves.iter().map(|w| w.id).sorted().collect_vec(), [1, 2]);
+        assert_eq!(
+            ct.log
+                .iter()
+                .filter(|e| matches!(e.event, CombatEvent::Joined { .. }))
+                .count(),
+            3
+        );
+
+        ct.undo();
+        assert_eq!(ct.entities.len(), 3);
+        assert_eq!(ct.entities[ct.current_turn].name, "Orc");
+    }
+
     #[test]
     fn test_remove_entry_by_uuid() {
         let mut ct = CombatTracker::new();
@@ -296,49 +1807,1980 @@ mod tests {
         assert_eq!(ct.entities.len(), 2);
     }
 
+    /// A tracker with entities `A`..`E` in initiative order, `turn` is the current turn
+    fn tracker_with_turn(turn: usize) -> CombatTracker {
+        let mut ct = CombatTracker::new();
+        for (i, name) in ["A", "B", "C", "D", "E"].into_iter().enumerate() {
+            let mut entity = Entity::new(name, EntityType::Monster, 0, 10, 10);
+            entity.initiative = Some(20 - i as i32);
+            ct.add_entity(entity);
+        }
+        ct.current_turn = turn;
+        ct.round = 1;
+        ct
+    }
+
+    fn current_name(ct: &CombatTracker) -> &str {
+        &ct.entities[ct.current_turn].name
+    }
+
     #[test]
-    fn test_from_yaml() {
-        let yaml_content = "
-players:
-  - name: Arthas
-    entity_type: Player
-    initiative_modifier: 2
-    ac: 18
-    max_hp: 45
-    current_hp: 45
-    conditions: []
+    fn test_remove_keeps_current_turn() {
+        // removing an entity before the current one
+        let mut ct = tracker_with_turn(2);
+        let uuid = ct.entities[0].uuid;
+        ct.execute(Command::RemoveEntity { target: uuid });
+        assert_eq!(current_name(&ct), "C");
+        assert_eq!(ct.round, 1);
 
-monsters:
-  - count: 3
-    stats:
-        name: Goblin
-        entity_type: Monster
-        initiative_modifier: 1
-        ac: 13
-        max_hp: 15
-        current_hp: 15
-        conditions: []
-  - stats:
-        name: Orc
-        entity_type: Monster
-        initiative_modifier: 1
-        ac: 13
-        max_hp: 15
-        conditions: [Blinded, Grappled]
-        ";
-        let tracker = CombatTracker::from_yaml(yaml_content.to_string());
+        // removing an entity after the current one
+        let uuid = ct.entities[3].uuid;
+        ct.execute(Command::RemoveEntity { target: uuid });
+        assert_eq!(current_name(&ct), "C");
+        assert_eq!(ct.entities.len(), 3);
+        assert!(ct.get_entity_by_uuid(uuid).is_none());
 
-        assert_eq!(tracker.entities.len(), 5);
-        assert!(tracker.entities.iter().any(|e| e.name == "Arthas"));
+        // removing it again changes nothing
+        assert!(!ct.execute(Command::RemoveEntity { target: uuid }));
+        assert_eq!(ct.entities.len(), 3);
+
+        ct.undo();
+        ct.undo();
+        assert_eq!(ct.entities.len(), 5);
+        assert_eq!(current_name(&ct), "C");
+    }
+
+    #[test]
+    fn test_remove_current_entity() {
+        // the turn passes on to the next entity
+        let mut ct = tracker_with_turn(2);
+        let uuid = ct.entities[2].uuid;
+        ct.remove_entity_by_uuid(uuid);
+        assert_eq!(current_name(&ct), "D");
+        assert_eq!(ct.round, 1);
+
+        // the last entity's turn passes on to the first one in the next round
+        let mut ct = tracker_with_turn(4);
+        let uuid = ct.entities[4].uuid;
+        ct.remove_entity_by_uuid(uuid);
+        assert_eq!(current_name(&ct), "A");
+        assert_eq!(ct.round, 2);
+
+        // defeated monsters are skipped
+        let mut ct = tracker_with_turn(1);
+        let c = ct.entities[2].uuid;
+        ct.damage(c, 10, None, false);
+        let uuid = ct.entities[1].uuid;
+        ct.remove_entity_by_uuid(uuid);
+        assert_eq!(current_name(&ct), "D");
+
+        // removing everyone
+        let mut ct = tracker_with_turn(3);
+        for uuid in ct.entities.iter().map(|e| e.uuid).collect_vec() {
+            ct.remove_entity_by_uuid(uuid);
+        }
+        assert!(ct.entities.is_empty());
+        assert_eq!(ct.current_turn, 0);
+        assert!(ct.get_current_entity().is_none());
+    }
+
+    #[test]
+    fn test_remove_current_entity_ends_its_turn() {
+        let mut ct = tracker_with_turn(2);
+        let (c, d) = (ct.entities[2].uuid, ct.entities[3].uuid);
+        ct.entities[2].triggers = vec!["end Make a WIS save".parse().unwrap()];
+        ct.entities[3].triggers = vec!["start Regenerate".parse().unwrap()];
+        ct.execute(Command::ToggleCondition {
+            target: d,
+            condition: Condition::Frightened,
+            duration: Some(ConditionDuration::EndOfSourceTurn {
+                source: c,
+                skip_current: false,
+            }),
+        });
+
+        ct.remove_entity_by_uuid(c);
+        assert_eq!(current_name(&ct), "D");
+        assert!(ct.entities[2].conditions.is_empty());
+        assert!(ct.entities[2].condition_durations.is_empty());
+        // only the reminder of the new turn is left
+        assert_eq!(ct.next_trigger().unwrap().target, d);
+        assert!(ct.next_trigger().is_none());
+    }
+
+    #[test]
+    fn test_remove_source_expires_its_durations() {
+        let mut ct = tracker_with_turn(0);
+        let (b, c, d) = (
+            ct.entities[1].uuid,
+            ct.entities[2].uuid,
+            ct.entities[3].uuid,
+        );
+        // applied during the caster's turn, so the first end of its turn doesn't count
+        ct.execute(Command::ToggleCondition {
+            target: d,
+            condition: Condition::Paralyzed,
+            duration: Some(ConditionDuration::EndOfSourceTurn {
+                source: c,
+                skip_current: true,
+            }),
+        });
+        ct.execute(Command::ToggleCondition {
+            target: d,
+            condition: Condition::Poisoned,
+            duration: Some(ConditionDuration::Rounds(3)),
+        });
+        let mut hex = Effect::new("Hex");
+        hex.source = Some(c);
+        ct.execute(Command::AddEffect {
+            target: b,
+            effect: hex,
+        });
+
+        ct.execute(Command::RemoveEntity { target: c });
+        assert_eq!(current_name(&ct), "A");
+        let d = ct.get_entity_by_uuid(d).unwrap();
         assert_eq!(
-            tracker
-                .entities
-                .iter()
-                .filter(|e| e.name == "Goblin")
-                .count(),
-            3
+            d.conditions.iter().collect_vec(),
+            vec![&Condition::Poisoned]
         );
-        assert_eq!(tracker.entities.last().unwrap().current_hp, 15);
-        assert_eq!(tracker.entities.last().unwrap().conditions.len(), 2);
+        assert_eq!(d.condition_durations.len(), 1);
+        assert!(ct.get_entity_by_uuid(b).unwrap().effects.is_empty());
+        let expired: Vec<String> = ct
+            .log
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.event,
+                    CombatEvent::ConditionExpired(_) | CombatEvent::EffectExpired(_)
+                )
+            })
+            .map(|entry| entry.to_string())
+            .collect();
+        assert_eq!(
+            expired,
+            vec![
+                "R1 T1 A -> B: Hex expired",
+                "R1 T1 A -> D: Paralyzed expired"
+            ]
+        );
+
+        // undo brings them back
+        ct.undo();
+        assert!(!ct.get_entity_by_uuid(b).unwrap().effects.is_empty());
+    }
+
+    #[test]
+    fn test_sort_keeps_current_turn() {
+        let mut ct = tracker_with_turn(1);
+        let b = ct.entities[1].uuid;
+        let d = ct.entities[3].uuid;
+        ct.execute(Command::SetInitiative {
+            target: d,
+            initiative: 30,
+        });
+        assert_eq!(current_name(&ct), "B");
+        assert_eq!(ct.entities[0].name, "D");
+        ct.execute(Command::SetInitiative {
+            target: b,
+            initiative: 1,
+        });
+        assert_eq!(current_name(&ct), "B");
+        ct.execute(Command::RollInitiative {
+            group_by_name: false,
+            re_roll: true,
+        });
+        assert_eq!(current_name(&ct), "B");
+
+        // before the first turn the new order decides who starts
+        let mut ct = tracker_with_turn(0);
+        ct.round = 0;
+        let e = ct.entities[4].uuid;
+        ct.set_initiative(e, 30);
+        assert_eq!(current_name(&ct), "E");
+    }
+
+    #[derive(Debug, Clone)]
+    enum TurnOp {
+        SetInitiative(Index, i32),
+        RollInitiative { group_by_name: bool },
+        Add(Option<i32>),
+        Remove(Index),
+        NextTurn,
+        PrevTurn,
+        Undo,
+    }
+
+    fn turn_op() -> impl Strategy<Value = TurnOp> {
+        prop_oneof![
+            (any::<Index>(), -5..30).prop_map(|(i, ini)| TurnOp::SetInitiative(i, ini)),
+            any::<bool>().prop_map(|group_by_name| TurnOp::RollInitiative { group_by_name }),
+            proptest::option::of(-5..30).prop_map(TurnOp::Add),
+            any::<Index>().prop_map(TurnOp::Remove),
+            Just(TurnOp::NextTurn),
+            Just(TurnOp::PrevTurn),
+            Just(TurnOp::Undo),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_reordering_keeps_current_turn(ops in prop::collection::vec(turn_op(), 1..40)) {
+            let mut ct = tracker_with_turn(2);
+            for op in ops {
+                let started = !ct.is_before_first_turn();
+                let current = ct.current_uuid();
+                let uuids = ct.entities.iter().map(|e| e.uuid).collect_vec();
+                // the entity whose turn it is afterwards, if the op must not change it
+                let expected = match &op {
+                    TurnOp::SetInitiative(i, initiative) if !uuids.is_empty() => {
+                        ct.execute(Command::SetInitiative {
+                            target: uuids[i.index(uuids.len())],
+                            initiative: *initiative,
+                        });
+                        current.filter(|_| started)
+                    }
+                    TurnOp::RollInitiative { group_by_name } => {
+                        ct.execute(Command::RollInitiative {
+                            group_by_name: *group_by_name,
+                            re_roll: true,
+                        });
+                        current.filter(|_| started)
+                    }
+                    TurnOp::Add(initiative) => {
+                        let mut entity = Entity::new("X", EntityType::Monster, 0, 10, 10);
+                        entity.initiative = *initiative;
+                        ct.execute(Command::AddEntities(vec![entity]));
+                        current.filter(|_| started)
+                    }
+                    TurnOp::Remove(i) if !uuids.is_empty() => {
+                        let index = i.index(uuids.len());
+                        ct.execute(Command::RemoveEntity { target: uuids[index] });
+                        if Some(uuids[index]) == current {
+                            // the turn passes on to the next entity
+                            (uuids.len() > 1)
+                                .then(|| uuids[(index + 1) % uuids.len()])
+                        } else {
+                            current
+                        }
+                    }
+                    TurnOp::NextTurn => {
+                        ct.execute(Command::NextTurn);
+                        None
+                    }
+                    TurnOp::PrevTurn => {
+                        ct.execute(Command::PrevTurn);
+                        None
+                    }
+                    TurnOp::Undo => {
+                        ct.undo();
+                        None
+                    }
+                    _ => None,
+                };
+                if ct.entities.is_empty() {
+                    prop_assert_eq!(ct.current_turn, 0);
+                } else {
+                    prop_assert!(ct.current_turn < ct.entities.len());
+                }
+                if let Some(expected) = expected {
+                    prop_assert_eq!(ct.current_uuid(), Some(expected), "after {:?}", op);
+                }
+                prop_assert!(ct
+                    .entities
+                    .windows(2)
+                    .all(|w| w[0].initiative >= w[1].initiative));
+            }
+        }
+    }
+
+    #[test]
+    fn test_delay_and_resume() {
+        let mut ct = tracker_with_turn(1);
+        let b = ct.entities[1].uuid;
+        let d = ct.entities[3].uuid;
+
+        // B delays, C takes over and B is skipped from now on
+        ct.execute(Command::DelayTurn);
+        assert_eq!(current_name(&ct), "C");
+        assert!(ct.entities[1].delaying);
+        assert_eq!(ct.entities[1].conditions_string(), "Delaying");
+        for _ in 0..3 {
+            ct.next_turn();
+        }
+        assert_eq!(current_name(&ct), "A");
+        assert_eq!(ct.round, 2);
+        ct.next_turn();
+        assert_eq!(current_name(&ct), "C");
+
+        // B acts after D
+        ct.execute(Command::ResumeTurn {
+            target: b,
+            after: d,
+        });
+        assert_eq!(current_name(&ct), "C");
+        assert_eq!(
+            ct.entities.iter().map(|e| e.name.as_str()).join(""),
+            "ACDBE"
+        );
+        assert_eq!(ct.entities[3].initiative, ct.entities[2].initiative);
+        assert!(!ct.entities[3].delaying);
+        ct.next_turn();
+        ct.next_turn();
+        assert_eq!(current_name(&ct), "B");
+
+        // only delaying entities can be resumed
+        let a = ct.entities[0].uuid;
+        ct.execute(Command::ResumeTurn {
+            target: a,
+            after: d,
+        });
+        assert_eq!(ct.entities[0].name, "A");
+
+        ct.undo();
+        assert!(ct.entities[1].delaying);
+        assert_eq!(current_name(&ct), "C");
+    }
+
+    #[test]
+    fn test_delay_needs_someone_to_take_over() {
+        // everyone else is defeated and skipped
+        let mut ct = tracker_with_turn(1);
+        for uuid in ct.entities.iter().map(|e| e.uuid).collect_vec() {
+            if uuid != ct.entities[1].uuid {
+                ct.damage(uuid, 10, None, false);
+            }
+        }
+        assert!(!ct.execute(Command::DelayTurn));
+        assert_eq!(current_name(&ct), "B");
+        assert!(!ct.entities[1].delaying);
+
+        // the only combatant, the lair step doesn't take over
+        let mut ct = CombatTracker::new();
+        let mut dragon = Entity::new("Dragon", EntityType::Monster, 0, 18, 100);
+        dragon.initiative = Some(15);
+        dragon.lair_actions = vec!["Tremor".to_string()];
+        ct.add_entities(vec![dragon]);
+        ct.current_turn = 1;
+        ct.round = 1;
+        assert_eq!(current_name(&ct), "Dragon");
+        assert!(!ct.execute(Command::DelayTurn));
+        assert!(!ct.entities[1].delaying);
+
+        // the lair step can't delay
+        ct.current_turn = 0;
+        assert_eq!(ct.entities[0].entity_type, EntityType::Lair);
+        assert!(!ct.execute(Command::DelayTurn));
+        assert_eq!(ct.current_turn, 0);
+        assert!(ct.entities.iter().all(|e| !e.delaying));
+    }
+
+    #[test]
+    fn test_resume_keeps_order_when_sorting() {
+        let mut ct = CombatTracker::new();
+        let entities = [
+            ("Merry", EntityType::Player, 20),
+            ("Goblin", EntityType::Monster, 20),
+            ("Dragon", EntityType::Monster, 15),
+        ]
+        .map(|(name, entity_type, initiative)| {
+            let mut entity = Entity::new(name, entity_type, 0, 10, 10);
+            entity.initiative = Some(initiative);
+            if name == "Dragon" {
+                entity.lair_actions = vec!["Tremor".to_string()];
+            }
+            entity
+        });
+        ct.add_entities(entities.to_vec());
+        let order = |ct: &CombatTracker| ct.entities.iter().map(|e| e.name.as_str()).join(", ");
+        assert_eq!(order(&ct), "Merry, Goblin, Lair, Dragon");
+        let (merry, goblin, lair) = (
+            ct.entities[0].uuid,
+            ct.entities[1].uuid,
+            ct.entities[2].uuid,
+        );
+        ct.round = 1;
+
+        // Merry acts after the Goblin with the same initiative, even after sorting again
+        ct.execute(Command::DelayTurn);
+        ct.execute(Command::ResumeTurn {
+            target: merry,
+            after: goblin,
+        });
+        ct.sort_by_initiative();
+        assert_eq!(order(&ct), "Goblin, Merry, Lair, Dragon");
+        assert_eq!(current_name(&ct), "Goblin");
+
+        // the order survives saving and loading
+        let loaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(order(&loaded), "Goblin, Merry, Lair, Dragon");
+        assert_eq!(current_name(&loaded), "Goblin");
+
+        // resuming after the lair
+        ct.execute(Command::DelayTurn);
+        ct.execute(Command::ResumeTurn {
+            target: goblin,
+            after: lair,
+        });
+        ct.sort_by_initiative();
+        assert_eq!(order(&ct), "Merry, Lair, Goblin, Dragon");
+        assert_eq!(current_name(&ct), "Merry");
+        let loaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(order(&loaded), "Merry, Lair, Goblin, Dragon");
+    }
+
+    #[test]
+    fn test_readied_action() {
+        let mut ct = tracker_with_turn(1);
+        let b = ct.entities[1].uuid;
+        ct.execute(Command::Ready {
+            target: b,
+            action: "attack when the door opens".to_string(),
+        });
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Readied (attack when the door opens)"
+        );
+        ct.next_turn();
+        ct.execute(Command::UseReadied { target: b });
+        assert!(ct.entities[1].readied.is_none());
+        assert_eq!(
+            ct.log.last().unwrap().to_string(),
+            "R1 T3 C -> B: uses readied attack when the door opens"
+        );
+
+        // an unused readied action expires at the start of the entity's next turn
+        ct.execute(Command::Ready {
+            target: b,
+            action: "Fire Bolt".to_string(),
+        });
+        for _ in 0..4 {
+            ct.next_turn();
+        }
+        assert_eq!(current_name(&ct), "B");
+        assert!(ct.entities[1].readied.is_none());
+        assert_eq!(
+            ct.log.last().unwrap().event,
+            CombatEvent::ReadiedExpired("Fire Bolt".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lair_cannot_be_targeted() {
+        let mut ct = CombatTracker::new();
+        let mut dragon = Entity::new("Dragon", EntityType::Monster, 0, 19, 200);
+        dragon.initiative = Some(15);
+        dragon.lair_actions = vec!["Tremor".to_string()];
+        ct.add_entities(vec![dragon]);
+        let lair = ct.entities[0].uuid;
+        assert_eq!(ct.entities[0].entity_type, EntityType::Lair);
+        let log_len = ct.log.len();
+
+        let commands = [
+            Command::SetInitiative {
+                target: lair,
+                initiative: 3,
+            },
+            Command::ToggleCondition {
+                target: lair,
+                condition: Condition::Prone,
+                duration: None,
+            },
+            Command::Damage {
+                target: lair,
+                amount: 5,
+                damage_type: None,
+                critical: false,
+            },
+            Command::SetTempHp {
+                target: lair,
+                amount: 5,
+            },
+            Command::AddEffect {
+                target: lair,
+                effect: Effect::new("Bless"),
+            },
+            Command::StartConcentration {
+                target: lair,
+                spell: "Bless".to_string(),
+            },
+            Command::RemoveEntity { target: lair },
+        ];
+        for command in commands {
+            assert!(!ct.execute(command.clone()), "{command:?} was recorded");
+        }
+        assert_eq!(ct.entities[0].uuid, lair);
+        assert_eq!(ct.entities[0].initiative, Some(LAIR_INITIATIVE));
+        assert_eq!(ct.log.len(), log_len);
+        assert!(!ct.can_undo());
+    }
+
+    #[test]
+    fn test_legendary_and_lair_actions() {
+        let yaml_content = "
+players:
+  - name: Merry
+    initiative: 20
+    initiative_modifier: 2
+    ac: 16
+    max_hp: 40
+monsters:
+  - stats:
+        name: Goblin
+        initiative: 20
+        initiative_modifier: 2
+        ac: 13
+        max_hp: 7
+  - stats:
+        name: Dragon
+        initiative: 15
+        initiative_modifier: 0
+        ac: 19
+        max_hp: 200
+        legendary_actions: 3
+        lair_actions:
+          - Magma erupts
+          - Tremor
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let names = |ct: &CombatTracker| ct.entities.iter().map(|e| e.name.clone()).join(", ");
+        assert_eq!(names(&ct), "Merry, Goblin, Lair, Dragon");
+        assert_eq!(ct.lair_actions().len(), 2);
+        assert_eq!(ct.entities[2].ref_array_string()[2], "");
+        let dragon = ct.entities[3].uuid;
+
+        // the lair keeps its initiative and is never skipped
+        ct.roll_initiative(true, true);
+        let lair = ct.entities.iter().find(|e| e.name == "Lair").unwrap();
+        assert_eq!(lair.initiative, Some(LAIR_INITIATIVE));
+        ct.roll_initiative(true, true);
+        for (name, initiative) in [("Merry", 20), ("Goblin", 20), ("Dragon", 15)] {
+            let uuid = ct.entities.iter().find(|e| e.name == name).unwrap().uuid;
+            ct.set_initiative(uuid, initiative);
+        }
+        assert_eq!(names(&ct), "Merry, Goblin, Lair, Dragon");
+
+        ct.execute(Command::UseLegendaryAction {
+            target: dragon,
+            cost: 2,
+        });
+        ct.execute(Command::UseLegendaryAction {
+            target: dragon,
+            cost: 2,
+        });
+        assert_eq!(ct.entities[3].legendary_actions_remaining(), 1);
+        assert_eq!(ct.entities[3].conditions_string(), "Legendary 1/3");
+
+        // the saved turn points at the same step after loading
+        ct.next_turn();
+        ct.next_turn();
+        assert_eq!(current_name(&ct), "Lair");
+        let state = PlayerClientState::from(&ct);
+        assert_eq!(
+            state.entities.iter().map(|e| e.name.as_str()).join(", "),
+            "Merry, Goblin, Dragon"
+        );
+        assert_eq!(state.current_turn, 3);
+        let yaml = ct.to_yaml();
+        assert!(!yaml.contains("name: Lair"));
+        let loaded = CombatTracker::from_yaml(yaml);
+        assert_eq!(current_name(&loaded), "Lair");
+        assert_eq!(loaded.entities[3].legendary_actions_used, 2);
+
+        // the pool is refreshed at the start of the dragon's turn
+        ct.next_turn();
+        assert_eq!(current_name(&ct), "Dragon");
+        assert_eq!(ct.entities[3].legendary_actions_remaining(), 3);
+
+        // the lair step goes away with the last entity that has lair actions
+        ct.execute(Command::RemoveEntity { target: dragon });
+        assert_eq!(names(&ct), "Merry, Goblin");
+        assert!(!ct
+            .log
+            .iter()
+            .any(|entry| entry.target.as_deref() == Some("Lair")));
+    }
+
+    #[test]
+    fn test_turn_triggers() {
+        let yaml_content = r#"
+players:
+  - name: Merry
+    initiative: 20
+    initiative_modifier: 2
+    ac: 16
+    max_hp: 40
+    effects:
+      - name: Hold Person
+        triggers:
+          - when: end
+            text: Make a WIS save
+monsters:
+  - stats:
+        name: Troll
+        initiative: 10
+        initiative_modifier: 1
+        ac: 15
+        max_hp: 84
+        current_hp: 50
+        triggers:
+          - when: start
+            text: Regenerate
+            heal: "10"
+        "#;
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let troll = ct.entities[1].uuid;
+        assert!(ct.next_trigger().is_none());
+
+        ct.execute(Command::NextTurn);
+        let hold_person = ct.next_trigger().unwrap();
+        assert_eq!(hold_person.effect.as_deref(), Some("Hold Person"));
+        assert_eq!(
+            hold_person.to_string(),
+            "Hold Person, end of turn: Make a WIS save"
+        );
+        assert_eq!(ct.apply_trigger(&hold_person), Ok(None));
+
+        // regeneration is applied as its own command and can be undone
+        let regenerate = ct.next_trigger().unwrap();
+        assert_eq!(regenerate.target, troll);
+        assert!(ct.next_trigger().is_none());
+        let result = ct.apply_trigger(&regenerate).unwrap().unwrap();
+        assert_eq!(result.roll.total, 10);
+        assert!(result.applied);
+        assert_eq!(ct.entities[1].current_hp, 60);
+        ct.undo();
+        assert_eq!(ct.entities[1].current_hp, 50);
+
+        // healing at full hit points isn't recorded, there is nothing to undo
+        ct.execute(Command::Heal {
+            target: troll,
+            amount: 34,
+        });
+        let result = ct.apply_trigger(&regenerate).unwrap().unwrap();
+        assert!(!result.applied);
+        assert_eq!(
+            ct.undo(),
+            Some(Command::Heal {
+                target: troll,
+                amount: 34,
+            })
+        );
+        assert_eq!(ct.entities[1].current_hp, 50);
+        assert_eq!(current_name(&ct), "Troll");
+
+        // triggers added by the DM fire too
+        let trigger: Trigger = "end Searing Smite damage 2d6 fire".parse().unwrap();
+        assert_eq!(trigger.text, "Searing Smite");
+        assert_eq!(trigger.damage.as_deref(), Some("2d6 fire"));
+        ct.execute(Command::AddTrigger {
+            target: troll,
+            trigger,
+        });
+        ct.execute(Command::NextTurn);
+        let smite = ct.next_trigger().unwrap();
+        assert_eq!(smite.effect, None);
+        ct.apply_trigger(&smite).unwrap();
+        assert!((38..=48).contains(&ct.entities[1].current_hp));
+        assert!(ct
+            .log
+            .iter()
+            .any(|e| e.event == CombatEvent::Triggered(smite.to_string())));
+
+        ct.execute(Command::RemoveTrigger {
+            target: troll,
+            index: 1,
+        });
+        assert_eq!(ct.entities[1].triggers.len(), 1);
+        // triggers that weren't shown are dropped on the next turn
+        ct.execute(Command::NextTurn);
+        ct.execute(Command::NextTurn);
+        assert!(ct.next_trigger().is_none());
+        ct.execute(Command::NextTurn);
+        assert_eq!(ct.next_trigger().unwrap().trigger.text, "Make a WIS save");
+        assert_eq!(ct.next_trigger().unwrap().trigger.text, "Regenerate");
+    }
+
+    #[test]
+    fn test_limited_abilities() {
+        let yaml_content = "
+players:
+  - name: Merry
+    initiative: 20
+    initiative_modifier: 2
+    ac: 16
+    max_hp: 40
+monsters:
+  - stats:
+        name: Dragon
+        initiative: 15
+        initiative_modifier: 0
+        ac: 19
+        max_hp: 200
+        limited_abilities:
+          - name: Fire Breath
+            recharge: 5
+          - name: Fireball
+            per_day: 2
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        ct.rng = StdRng::seed_from_u64(42);
+        let dragon = ct.entities[1].uuid;
+        let use_ability = |ct: &mut CombatTracker, name: &str| {
+            ct.execute(Command::UseAbility {
+                target: dragon,
+                name: name.to_string(),
+            })
+        };
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Fire Breath ●, Fireball ●●"
+        );
+
+        use_ability(&mut ct, "Fireball");
+        use_ability(&mut ct, "Fireball");
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Fire Breath ●, Fireball ○○"
+        );
+        // no uses left
+        assert!(ct.can_undo());
+        ct.undo();
+        use_ability(&mut ct, "Fireball");
+        let log_len = ct.log.len();
+        use_ability(&mut ct, "Fireball");
+        assert_eq!(ct.log.len(), log_len);
+
+        // a spent recharge ability is rolled for at the start of the dragon's turn
+        use_ability(&mut ct, "Fire Breath");
+        assert!(!ct.entities[1].limited_abilities[0].is_available());
+        let mut rolls = Vec::new();
+        for _ in 0..40 {
+            let start = ct.log.len();
+            ct.execute(Command::NextTurn);
+            assert_eq!(current_name(&ct), "Dragon");
+            for entry in &ct.log[start..] {
+                if let CombatEvent::Recharge { roll, success, .. } = entry.event {
+                    assert_eq!(success, roll >= 5);
+                    rolls.push(roll);
+                }
+            }
+            if ct.entities[1].limited_abilities[0].is_available() {
+                break;
+            }
+            ct.execute(Command::NextTurn);
+        }
+        assert!(rolls.last().is_some_and(|&roll| roll >= 5));
+        assert!(rolls[..rolls.len() - 1].iter().all(|&roll| roll < 5));
+        // per-day abilities don't recharge
+        assert_eq!(ct.entities[1].limited_abilities[1].used, 2);
+
+        ct.execute(Command::RestoreAbility {
+            target: dragon,
+            name: "Fireball".to_string(),
+        });
+        assert!(ct.entities[1].limited_abilities[1].is_available());
+        use_ability(&mut ct, "Fire Breath");
+        ct.reset_combat();
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Fire Breath ●, Fireball ●●"
+        );
+    }
+
+    #[test]
+    fn test_from_yaml_with_bestiary() {
+        let mut bestiary = Bestiary::new();
+        bestiary.insert(
+            serde_yml::from_str("{name: Goblin, initiative_modifier: 2, ac: 15, max_hp: 7}")
+                .unwrap(),
+        );
+        let yaml_content = "
+players: []
+monsters:
+  - count: 2
+    monster: goblin
+  - monster: goblin
+    stats:
+      current_hp: 3
+        ";
+        let ct =
+            CombatTracker::from_yaml_with_bestiary(yaml_content.to_string(), &bestiary).unwrap();
+        assert_eq!(ct.entities.len(), 3);
+        assert!(ct.entities.iter().all(|e| e.ac == 15 && e.max_hp == 7));
+        assert_eq!(
+            ct.entities
+                .iter()
+                .map(|e| e.current_hp)
+                .sorted()
+                .collect_vec(),
+            vec![3, 7, 7]
+        );
+
+        let error = CombatTracker::from_yaml_with_bestiary(
+            "{players: [], monsters: [{monster: Owlbear}]}".to_string(),
+            &bestiary,
+        )
+        .unwrap_err();
+        assert!(matches!(error, LoadError::UnknownMonster { .. }));
+        assert!(
+            CombatTracker::from_yaml_with_bestiary("monsters: 3".to_string(), &bestiary).is_err()
+        );
+    }
+
+    #[test]
+    fn test_ability_scores() {
+        let yaml_content = "
+players:
+  - name: Merry
+    initiative_modifier: 2
+    ac: 16
+    max_hp: 40
+    ability_scores: {str: 8, dex: 15, con: 12, wis: 13}
+    save_proficiencies: [dex]
+    proficiency_bonus: 3
+    saving_throws: {con: 5}
+    skills: {stealth: 8}
+monsters:
+  - stats:
+        name: Orc
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        ";
+        let ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let merry = &ct.entities[0];
+        assert_eq!(merry.ability_modifier(Ability::Str), Some(-1));
+        assert_eq!(merry.save_modifier(Ability::Dex), Some(5));
+        assert_eq!(merry.save_modifier(Ability::Con), Some(5));
+        assert_eq!(merry.save_modifier(Ability::Wis), Some(1));
+        assert_eq!(merry.save_modifier(Ability::Cha), None);
+        assert_eq!(merry.skill_modifier(Skill::Stealth), Some(8));
+        assert_eq!(merry.skill_modifier(Skill::Acrobatics), Some(2));
+        assert_eq!(merry.passive_perception(), Some(11));
+        assert_eq!(
+            merry.details(),
+            vec![
+                "Merry  AC 16  HP 40/40  Initiative +2  Passive Perception 11",
+                "STR 8 (-1)  DEX 15 (+2)  CON 12 (+1)  INT -  WIS 13 (+1)  CHA -",
+                "Saves: DEX +5, CON +5",
+                "Skills: Stealth +8",
+            ]
+        );
+        // nothing known but the basics
+        assert_eq!(ct.entities[1].details().len(), 1);
+        assert_eq!(ct.entities[1].save_modifier(Ability::Dex), None);
+
+        let yaml = ct.to_yaml();
+        let reloaded = CombatTracker::from_yaml(yaml);
+        assert_eq!(reloaded.entities[0].details(), merry.details());
+    }
+
+    #[test]
+    fn test_actions() {
+        let yaml_content = "
+players: []
+monsters:
+  - stats:
+        name: Goblin
+        initiative_modifier: 2
+        ac: 15
+        max_hp: 7
+        actions:
+          - name: Scimitar
+            to_hit: 4
+            damage: 1d6+2
+            damage_type: slashing
+          - name: Stinking Cloud
+            save_dc: 13
+            save_ability: con
+            description: A 20-foot-radius sphere of yellow gas
+          - name: Nimble Escape
+        ";
+        let ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let actions = &ct.entities[0].actions;
+        assert_eq!(
+            actions.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+            vec![
+                "Scimitar +4, 1d6+2 slashing",
+                "Stinking Cloud DC 13 CON",
+                "Nimble Escape"
+            ]
+        );
+        assert_eq!(
+            actions[0].damage_string().as_deref(),
+            Some("1d6+2 slashing")
+        );
+        assert_eq!(actions[1].damage_string(), None);
+
+        let reloaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(&reloaded.entities[0].actions, actions);
+    }
+
+    #[test]
+    fn test_parse_trigger() {
+        let trigger: Trigger = "end Make a WIS save vs Hold Person".parse().unwrap();
+        assert_eq!(
+            trigger,
+            Trigger::new(TurnPhase::End, "Make a WIS save vs Hold Person")
+        );
+        let trigger: Trigger = "Start Regenerate heal 10".parse().unwrap();
+        assert_eq!(trigger.when, TurnPhase::Start);
+        assert_eq!(trigger.heal.as_deref(), Some("10"));
+        assert_eq!(trigger.to_string(), "start of turn: Regenerate (heal 10)");
+        // a trailing keyword is part of the reminder
+        let trigger: Trigger = "start Resist the damage".parse().unwrap();
+        assert_eq!(trigger.text, "Resist the damage");
+        assert!("start heal 1dx".parse::<Trigger>().is_err());
+        assert!("sometimes Regenerate".parse::<Trigger>().is_err());
+        assert!("start".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn test_remove_ends_concentration() {
+        let mut ct = tracker_with_turn(0);
+        let a = ct.entities[0].uuid;
+        let b = ct.entities[1].uuid;
+        ct.start_concentration(a, "Hex");
+        ct.add_effect(
+            b,
+            Effect {
+                name: "Hex".to_string(),
+                value: None,
+                duration: None,
+                source: Some(a),
+                triggers: Vec::new(),
+            },
+        );
+        ct.remove_entity_by_uuid(a);
+        assert!(ct.entities[0].effects.is_empty());
+        assert_eq!(ct.log.last().unwrap().event, CombatEvent::Left);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut ct = CombatTracker::new();
+        let entity = Entity::new("monster", EntityType::Monster, 0, 10, 20);
+        let uuid = entity.uuid;
+        ct.add_entity(entity);
+
+        ct.execute(Command::Damage {
+            target: uuid,
+            amount: 45,
+            damage_type: None,
+            critical: false,
+        });
+        ct.execute(Command::ToggleCondition {
+            target: uuid,
+            condition: Condition::Prone,
+            duration: None,
+        });
+        ct.execute(Command::NextTurn);
+        assert_eq!(ct.entities[0].current_hp, 0);
+        assert_eq!(ct.round, 1);
+
+        assert_eq!(ct.undo(), Some(Command::NextTurn));
+        assert_eq!(ct.round, 0);
+        ct.undo();
+        assert!(ct.entities[0].conditions.is_empty());
+        ct.undo();
+        assert_eq!(ct.entities[0].current_hp, 20);
+        assert!(!ct.can_undo());
+        assert_eq!(ct.undo(), None);
+
+        ct.redo();
+        assert_eq!(ct.entities[0].current_hp, 0);
+        assert!(ct.can_redo());
+
+        // a new command discards the redo history
+        ct.execute(Command::Heal {
+            target: uuid,
+            amount: 5,
+        });
+        assert!(!ct.can_redo());
+        assert_eq!(ct.entities[0].current_hp, 5);
+    }
+
+    #[test]
+    fn test_undo_restores_removed_entity_and_initiative() {
+        let mut ct = CombatTracker::new();
+        let mut entity1 = Entity::new("monster1", EntityType::Monster, 0, 10, 20);
+        entity1.initiative = Some(10);
+        let mut entity2 = Entity::new("monster2", EntityType::Monster, 0, 10, 20);
+        entity2.initiative = Some(5);
+        let uuid2 = entity2.uuid;
+        ct.add_entity(entity1);
+        ct.add_entity(entity2);
+
+        ct.execute(Command::SetInitiative {
+            target: uuid2,
+            initiative: 15,
+        });
+        assert_eq!(ct.entities[0].name, "monster2");
+        ct.execute(Command::RemoveEntity { target: uuid2 });
+        assert_eq!(ct.entities.len(), 1);
+
+        ct.undo();
+        assert_eq!(ct.entities.len(), 2);
+        ct.undo();
+        assert_eq!(ct.entities[0].name, "monster1");
+    }
+
+    #[test]
+    fn test_noop_commands_are_not_recorded() {
+        let mut ct = CombatTracker::new();
+        ct.execute(Command::Damage {
+            target: Uuid::new_v4(),
+            amount: 5,
+            damage_type: None,
+            critical: false,
+        });
+        assert!(!ct.can_undo());
+    }
+
+    #[test]
+    fn test_temp_hp() {
+        let mut ct = CombatTracker::new();
+        let mut entity = Entity::new("player", EntityType::Player, 0, 10, 30);
+        entity.initiative = Some(10);
+        let uuid = entity.uuid;
+        ct.add_entity(entity);
+
+        ct.execute(Command::SetTempHp {
+            target: uuid,
+            amount: 5,
+        });
+        // temporary hit points don't stack
+        ct.execute(Command::SetTempHp {
+            target: uuid,
+            amount: 3,
+        });
+        assert_eq!(ct.entities[0].temp_hp, 5);
+
+        ct.execute(Command::Damage {
+            target: uuid,
+            amount: 3,
+            damage_type: None,
+            critical: false,
+        });
+        assert_eq!(ct.entities[0].temp_hp, 2);
+        assert_eq!(ct.entities[0].current_hp, 30);
+
+        ct.execute(Command::Damage {
+            target: uuid,
+            amount: 7,
+            damage_type: None,
+            critical: false,
+        });
+        assert_eq!(ct.entities[0].temp_hp, 0);
+        assert_eq!(ct.entities[0].current_hp, 25);
+        assert!(matches!(
+            ct.log.last().unwrap().event,
+            CombatEvent::Damage {
+                temp_hp_absorbed: 2,
+                ..
+            }
+        ));
+
+        // healing doesn't restore temporary hit points
+        ct.execute(Command::Heal {
+            target: uuid,
+            amount: 10,
+        });
+        assert_eq!(ct.entities[0].temp_hp, 0);
+        assert_eq!(ct.entities[0].current_hp, 30);
+
+        ct.execute(Command::SetTempHp {
+            target: uuid,
+            amount: 8,
+        });
+        assert_eq!(ct.entities[0].ref_array_string()[2], "30/30 +8");
+
+        // temporary hit points survive a save
+        let reloaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(reloaded.entities[0].temp_hp, 8);
+        let loaded = CombatTracker::from_yaml(
+            "{players: [{name: Arthas, initiative_modifier: 2, ac: 18, max_hp: 45, temp_hp: 4}], monsters: []}"
+                .to_string(),
+        );
+        assert_eq!(loaded.entities[0].temp_hp, 4);
+
+        // negative temporary hit points from a hand edited file absorb nothing
+        ct.entities[0].temp_hp = -3;
+        ct.execute(Command::Damage {
+            target: uuid,
+            amount: 5,
+            damage_type: None,
+            critical: false,
+        });
+        assert_eq!(ct.entities[0].temp_hp, -3);
+        assert_eq!(ct.entities[0].current_hp, 25);
+    }
+
+    #[test]
+    fn test_damage_types() {
+        let yaml_content = "
+players: []
+monsters:
+  - stats:
+        name: Fire Elemental
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 100
+        resistances: [slashing]
+        vulnerabilities: [cold]
+        immunities: [fire, Poison]
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let uuid = ct.entities[0].uuid;
+        let damage = |ct: &mut CombatTracker, amount, damage_type| {
+            ct.execute(Command::Damage {
+                target: uuid,
+                amount,
+                damage_type,
+                critical: false,
+            });
+            ct.entities[0].current_hp
+        };
+
+        assert_eq!(damage(&mut ct, 15, Some(DamageType::Slashing)), 93);
+        assert!(matches!(
+            &ct.log.last().unwrap().event,
+            CombatEvent::Damage {
+                amount: 7,
+                adjusted_from: Some(15),
+                modifiers,
+                ..
+            } if modifiers == &vec![DamageModifier::Resistant]
+        ));
+        assert_eq!(damage(&mut ct, 5, Some(DamageType::Cold)), 83);
+        assert_eq!(damage(&mut ct, 50, Some(DamageType::Fire)), 83);
+        assert_eq!(damage(&mut ct, 50, Some(DamageType::Poison)), 83);
+        assert_eq!(damage(&mut ct, 3, Some(DamageType::Piercing)), 80);
+        assert_eq!(damage(&mut ct, 3, None), 77);
+
+        // the outcome can be looked up without applying the damage
+        ct.entities[0].temp_hp = 4;
+        let outcome = ct.damage_outcome(uuid, 5, Some(DamageType::Cold)).unwrap();
+        assert_eq!(outcome.amount, 10);
+        assert_eq!(outcome.adjusted_from, Some(5));
+        assert_eq!(outcome.modifiers, vec![DamageModifier::Vulnerable]);
+        assert_eq!(outcome.temp_hp_absorbed, 4);
+        assert_eq!(
+            outcome.to_string(),
+            "10 cold damage [vulnerable, doubled from 5], 4 absorbed by temp HP"
+        );
+        assert_eq!(ct.entities[0].current_hp, 77);
+
+        // doubling huge damage doesn't overflow
+        assert_eq!(damage(&mut ct, 2_000_000_000, Some(DamageType::Cold)), 0);
+        assert!(matches!(
+            &ct.log.last().unwrap().event,
+            CombatEvent::Damage {
+                amount: i32::MAX,
+                adjusted_from: Some(2_000_000_000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_timed_conditions() {
+        let mut ct = CombatTracker::new();
+        let mut caster = Entity::new("caster", EntityType::Player, 0, 10, 30);
+        caster.initiative = Some(20);
+        let mut monster = Entity::new("monster", EntityType::Monster, 0, 10, 20);
+        monster.initiative = Some(10);
+        let caster_uuid = caster.uuid;
+        let monster_uuid = monster.uuid;
+        ct.add_entity(caster);
+        ct.add_entity(monster);
+
+        // applied during the caster's turn: lasts until the end of its next turn
+        ct.execute(Command::ToggleCondition {
+            target: monster_uuid,
+            condition: Condition::Frightened,
+            duration: Some(ConditionDuration::EndOfSourceTurn {
+                source: caster_uuid,
+                skip_current: false,
+            }),
+        });
+        ct.execute(Command::ToggleCondition {
+            target: monster_uuid,
+            condition: Condition::Stunned,
+            duration: Some(ConditionDuration::Rounds(2)),
+        });
+        ct.execute(Command::ToggleCondition {
+            target: monster_uuid,
+            condition: Condition::Restrained,
+            duration: Some(ConditionDuration::UntilSave),
+        });
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Frightened (EoT), Restrained (save), Stunned (2)"
+        );
+
+        // the durations survive the save file
+        let loaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(
+            loaded.entities[1].condition_durations,
+            ct.entities[1].condition_durations
+        );
+
+        ct.execute(Command::NextTurn); // end of caster's turn
+        ct.execute(Command::NextTurn); // end of monster's turn
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Frightened (EoT), Restrained (save), Stunned (1)"
+        );
+        ct.execute(Command::NextTurn); // end of caster's next turn
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Restrained (save), Stunned (1)"
+        );
+        ct.execute(Command::NextTurn);
+        assert_eq!(ct.entities[1].conditions_string(), "Restrained (save)");
+        assert_eq!(
+            ct.log.last().unwrap().event,
+            CombatEvent::ConditionExpired(Condition::Stunned)
+        );
+
+        // undo brings expired conditions back
+        ct.undo();
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Restrained (save), Stunned (1)"
+        );
+    }
+
+    #[test]
+    fn test_custom_effects() {
+        let yaml_content = "
+players:
+  - name: Arthas
+    initiative: 15
+    initiative_modifier: 2
+    ac: 18
+    max_hp: 45
+    effects:
+      - name: Exhaustion
+        value: 2
+monsters:
+  - stats:
+        name: Orc
+        initiative: 10
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        conditions: [Prone]
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let arthas = ct.entities[0].uuid;
+        let orc = ct.entities[1].uuid;
+        assert_eq!(ct.entities[0].conditions_string(), "Exhaustion 2");
+
+        ct.execute(Command::AddEffect {
+            target: arthas,
+            effect: Effect {
+                name: "exhaustion".to_string(),
+                value: Some(3),
+                duration: None,
+                source: None,
+                triggers: Vec::new(),
+            },
+        });
+        ct.execute(Command::AddEffect {
+            target: orc,
+            effect: Effect {
+                name: "Hunter's Mark".to_string(),
+                value: None,
+                duration: Some(ConditionDuration::Rounds(1)),
+                source: Some(arthas),
+                triggers: Vec::new(),
+            },
+        });
+        assert_eq!(ct.entities[0].conditions_string(), "exhaustion 3");
+        assert_eq!(
+            ct.entities[1].conditions_string(),
+            "Prone, Hunter's Mark (1)"
+        );
+        // players only see the name and value
+        let state = PlayerClientState::from(&ct);
+        assert_eq!(state.entities[1].effects[0].to_string(), "Hunter's Mark");
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(!json.contains(&arthas.to_string()));
+
+        ct.execute(Command::NextTurn);
+        ct.execute(Command::NextTurn);
+        assert_eq!(ct.entities[1].conditions_string(), "Prone");
+        assert_eq!(
+            ct.log.last().unwrap().event,
+            CombatEvent::EffectExpired("Hunter's Mark".to_string())
+        );
+
+        ct.execute(Command::RemoveEffect {
+            target: arthas,
+            name: "EXHAUSTION".to_string(),
+        });
+        assert!(ct.entities[0].effects.is_empty());
+    }
+
+    #[test]
+    fn test_concentration() {
+        let yaml_content = "
+players:
+  - name: Cleric
+    initiative: 15
+    initiative_modifier: 2
+    ac: 18
+    max_hp: 45
+    current_hp: 45
+    saving_throws:
+      con: 5
+monsters:
+  - count: 2
+    stats:
+        name: Orc
+        initiative: 10
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        ct.rng = StdRng::seed_from_u64(42);
+        let cleric = ct.entities[0].uuid;
+        let orc1 = ct.entities[1].uuid;
+        let orc2 = ct.entities[2].uuid;
+
+        ct.execute(Command::StartConcentration {
+            target: cleric,
+            spell: "Bane".to_string(),
+        });
+        for orc in [orc1, orc2] {
+            ct.execute(Command::AddEffect {
+                target: orc,
+                effect: Effect {
+                    name: "Bane".to_string(),
+                    value: None,
+                    duration: None,
+                    source: Some(cleric),
+                    triggers: Vec::new(),
+                },
+            });
+        }
+        ct.execute(Command::AddEffect {
+            target: orc1,
+            effect: Effect::new("Bless"),
+        });
+        assert_eq!(ct.entities[0].conditions_string(), "Concentrating (Bane)");
+
+        ct.execute(Command::Damage {
+            target: cleric,
+            amount: 30,
+            damage_type: None,
+            critical: false,
+        });
+        assert_eq!(
+            ct.next_concentration_check(),
+            Some(ConcentrationCheck {
+                target: cleric,
+                spell: "Bane".to_string(),
+                dc: 15,
+            })
+        );
+        assert_eq!(ct.next_concentration_check(), None);
+
+        let (roll, success) = ct.saving_throw(cleric, Ability::Con, 15, None).unwrap();
+        assert!((6..=25).contains(&roll));
+        assert_eq!(success, roll >= 15);
+        assert_eq!(
+            ct.saving_throw(cleric, Ability::Con, 15, Some(3)),
+            Some((3, false))
+        );
+
+        ct.execute(Command::EndConcentration { target: cleric });
+        assert!(ct.entities[0].concentration.is_none());
+        assert_eq!(ct.entities[1].conditions_string(), "Bless");
+        assert_eq!(ct.entities[2].conditions_string(), "");
+
+        // dropping to 0 HP ends concentration without a save
+        ct.execute(Command::StartConcentration {
+            target: cleric,
+            spell: "Bless".to_string(),
+        });
+        ct.execute(Command::Damage {
+            target: cleric,
+            amount: 100,
+            damage_type: None,
+            critical: false,
+        });
+        assert!(ct.entities[0].concentration.is_none());
+        assert_eq!(ct.next_concentration_check(), None);
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut ct = CombatTracker::new();
+        let mut fighter = Entity::new("Fighter", EntityType::Player, 1, 18, 40);
+        fighter.initiative = Some(15);
+        let mut goblin = Entity::new("Goblin", EntityType::Monster, 2, 15, 7);
+        goblin.initiative = Some(12);
+        goblin.limited_abilities = vec![LimitedAbility {
+            name: "Poisoned Blade".to_string(),
+            recharge: None,
+            per_day: 1,
+            used: 0,
+        }];
+        let (attacker, target) = (goblin.uuid, fighter.uuid);
+        ct.add_entities(vec![fighter, goblin]);
+        let damage: DiceExpr = "1d6+2".parse().unwrap();
+        let damage = Some((&damage, Some(DamageType::Slashing)));
+
+        // total against the AC
+        let miss = ct
+            .attack(attacker, target, 4, damage, Some(13), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (miss.total, miss.ac, miss.hit, miss.damage),
+            (17, 18, false, None)
+        );
+        let hit = ct
+            .attack(attacker, target, 4, damage, Some(14), None)
+            .unwrap()
+            .unwrap();
+        assert!(hit.hit && !hit.critical);
+        let dealt = hit.damage.unwrap().total;
+        assert!((3..=8).contains(&dealt));
+        assert_eq!(hit.damage_taken.unwrap().amount, dealt);
+        assert_eq!(ct.entities[0].current_hp, 40 - dealt);
+
+        // natural 1 and 20 ignore the AC, a critical hit doubles the dice
+        assert!(
+            !ct.attack(attacker, target, 30, damage, Some(1), None)
+                .unwrap()
+                .unwrap()
+                .hit
+        );
+        let critical = ct
+            .attack(attacker, target, -10, damage, Some(20), None)
+            .unwrap()
+            .unwrap();
+        assert!(critical.hit && critical.critical);
+        let critical_damage = critical.damage.unwrap();
+        assert_eq!(critical_damage.terms[0].dice.len(), 2);
+        assert_eq!(
+            ct.entities[0].current_hp,
+            40 - dealt - critical_damage.total
+        );
+
+        // only the damage is undone
+        ct.undo();
+        assert_eq!(ct.entities[0].current_hp, 40 - dealt);
+        let attacks: Vec<String> = ct
+            .log
+            .iter()
+            .filter(|entry| matches!(entry.event, CombatEvent::Attack { .. }))
+            .map(|entry| entry.event.to_string())
+            .collect();
+        assert_eq!(
+            attacks,
+            vec![
+                "attacked by Goblin: 17 vs AC 18, miss",
+                "attacked by Goblin: 18 vs AC 18, hit",
+                "attacked by Goblin: natural 1, miss",
+                "attacked by Goblin: natural 20, critical hit",
+            ]
+        );
+
+        // an ability used for the attack is undone together with the damage
+        let uses = Command::UseAbility {
+            target: attacker,
+            name: "Poisoned Blade".to_string(),
+        };
+        ct.attack(attacker, target, 30, damage, Some(15), Some(uses))
+            .unwrap()
+            .unwrap();
+        assert!(!ct.entities[1].limited_abilities[0].is_available());
+        assert!(ct.entities[0].current_hp < 40 - dealt);
+        ct.undo();
+        assert!(ct.entities[1].limited_abilities[0].is_available());
+        assert_eq!(ct.entities[0].current_hp, 40 - dealt);
+
+        // a huge bonus doesn't overflow
+        let huge = ct
+            .attack(attacker, target, i32::MAX, None, Some(2), None)
+            .unwrap()
+            .unwrap();
+        assert!(huge.hit);
+        assert_eq!(huge.total, i32::MAX);
+        assert!(ct
+            .log
+            .last()
+            .unwrap()
+            .to_string()
+            .contains("2147483647 vs AC 18"));
+
+        // the damage taken is reported even if the hit changed nothing
+        ct.entities[0].immunities.insert(DamageType::Slashing);
+        let immune = ct
+            .attack(attacker, target, 30, damage, Some(15), None)
+            .unwrap()
+            .unwrap();
+        let taken = immune.damage_taken.unwrap();
+        assert_eq!(
+            (taken.amount, taken.modifiers),
+            (0, vec![DamageModifier::Immune])
+        );
+        assert_eq!(ct.entities[0].current_hp, 40 - dealt);
+    }
+
+    #[test]
+    fn test_group_save() {
+        let yaml_content = "
+players:
+  - name: Rogue
+    initiative: 20
+    initiative_modifier: 4
+    ac: 15
+    max_hp: 30
+    current_hp: 30
+    saving_throws:
+      dex: 30
+monsters:
+  - count: 2
+    stats:
+        name: Goblin
+        initiative: 10
+        initiative_modifier: 2
+        ac: 15
+        max_hp: 40
+        saving_throws:
+          dex: -30
+  - stats:
+        name: Fire Elemental
+        initiative: 5
+        initiative_modifier: 3
+        ac: 13
+        max_hp: 102
+        immunities: [fire]
+        saving_throws:
+          dex: -30
+        ";
+        let mut ct = CombatTracker::from_yaml(yaml_content.to_string());
+        let targets: Vec<Uuid> = ct.entities.iter().map(|e| e.uuid).collect();
+        let results = ct.group_save(&targets, Ability::Dex, 15, 25, Some(DamageType::Fire), None);
+        assert_eq!(
+            results
+                .iter()
+                .map(|r| (r.success, r.damage))
+                .collect::<Vec<_>>(),
+            vec![(true, 12), (false, 25), (false, 25), (false, 25)]
+        );
+        let hp: Vec<i32> = ct.entities.iter().map(|e| e.current_hp).collect();
+        assert_eq!(hp, vec![18, 15, 15, 102]);
+        assert_eq!(
+            ct.log
+                .iter()
+                .filter(|entry| matches!(entry.event, CombatEvent::SavingThrow { .. }))
+                .count(),
+            4
+        );
+
+        // the damage of all targets is undone in one step
+        assert!(matches!(ct.undo(), Some(Command::Batch(commands)) if commands.len() == 4));
+        let hp: Vec<i32> = ct.entities.iter().map(|e| e.current_hp).collect();
+        assert_eq!(hp, vec![30, 40, 40, 102]);
+        assert!(!ct.can_undo());
+        ct.redo();
+        assert_eq!(ct.entities[1].current_hp, 15);
+
+        // nothing is recorded without damage
+        let rogue = [targets[0]];
+        ct.group_save(&rogue, Ability::Dex, 15, 1, None, None);
+        assert_eq!(ct.entities[0].current_hp, 18);
+        ct.undo();
+        assert_eq!(ct.entities[0].current_hp, 30);
+    }
+
+    #[test]
+    fn test_kill() {
+        let mut ct = CombatTracker::new();
+        let mut player = Entity::new("Merry", EntityType::Player, 2, 16, 20);
+        player.initiative = Some(15);
+        player.temp_hp = 5;
+        player.concentration = Some("Bless".to_string());
+        let mut goblin = Entity::new("Goblin", EntityType::Monster, 1, 13, 7);
+        goblin.initiative = Some(10);
+        ct.add_entities(vec![player, goblin]);
+        let merry = ct.entities[0].uuid;
+        let goblin = ct.entities[1].uuid;
+
+        // a killed player is dead right away instead of rolling death saves
+        assert!(ct.execute(Command::Kill { target: merry }));
+        assert_eq!(ct.entities[0].current_hp, 0);
+        assert_eq!(ct.entities[0].temp_hp, 0);
+        assert!(ct.entities[0].is_dead());
+        assert!(!ct.entities[0].is_dying());
+        assert_eq!(ct.entities[0].concentration, None);
+        assert!(ct
+            .log
+            .iter()
+            .any(|entry| entry.event == CombatEvent::Killed));
+        assert!(!ct.execute(Command::Kill { target: merry }));
+
+        ct.execute(Command::Kill { target: goblin });
+        assert!(ct.entities[1].is_dead());
+
+        ct.undo();
+        ct.undo();
+        assert_eq!(ct.entities[0].current_hp, 20);
+        assert_eq!(ct.entities[0].temp_hp, 5);
+        assert_eq!(ct.entities[0].concentration.as_deref(), Some("Bless"));
+        assert!(ct.entities[0].death_saves.is_empty());
+    }
+
+    #[test]
+    fn test_death_saves() {
+        let mut ct = CombatTracker::new();
+        let mut player = Entity::new("Merry", EntityType::Player, 2, 16, 20);
+        player.initiative = Some(15);
+        let mut goblin = Entity::new("Goblin", EntityType::Monster, 1, 13, 7);
+        goblin.initiative = Some(10);
+        ct.add_entity(player);
+        ct.add_entity(goblin);
+        let merry = ct.entities[0].uuid;
+        let goblin = ct.entities[1].uuid;
+
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 25,
+            damage_type: None,
+            critical: false,
+        });
+        assert!(ct.entities[0].is_dying());
+        assert!(!ct.entities[0].is_dead());
+        assert_eq!(ct.entities[0].conditions_string(), "Dying S ○○○ F ○○○");
+
+        ct.execute(Command::DeathSave {
+            target: merry,
+            roll: 12,
+        });
+        ct.execute(Command::DeathSave {
+            target: merry,
+            roll: 5,
+        });
+        assert_eq!(ct.entities[0].death_saves.successes, 1);
+        assert_eq!(ct.entities[0].death_saves.failures, 1);
+        assert_eq!(
+            ct.log.last().unwrap().event.to_string(),
+            "death save 5: failure (1/3 successes, 1/3 failures)"
+        );
+
+        // damage at 0 HP is a failure, a natural 1 counts twice
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 3,
+            damage_type: None,
+            critical: false,
+        });
+        assert_eq!(ct.entities[0].death_saves.failures, 2);
+        ct.execute(Command::DeathSave {
+            target: merry,
+            roll: 1,
+        });
+        assert!(ct.entities[0].is_dead());
+        assert!(!ct.entities[0].is_dying());
+        assert_eq!(ct.entities[0].conditions_string(), "Dead");
+
+        // dead characters don't roll anymore and can't be healed
+        ct.execute(Command::DeathSave {
+            target: merry,
+            roll: 20,
+        });
+        assert_eq!(ct.entities[0].current_hp, 0);
+        ct.execute(Command::Heal {
+            target: merry,
+            amount: 10,
+        });
+        assert_eq!(ct.entities[0].current_hp, 0);
+        assert!(ct.entities[0].is_dead());
+
+        ct.undo();
+        ct.undo();
+        assert_eq!(ct.entities[0].death_saves.failures, 1);
+
+        // a natural 20 brings the character back with 1 HP
+        ct.execute(Command::DeathSave {
+            target: merry,
+            roll: 20,
+        });
+        assert_eq!(ct.entities[0].current_hp, 1);
+        assert!(ct.entities[0].death_saves.is_empty());
+
+        // three successes stabilize
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 1,
+            damage_type: None,
+            critical: false,
+        });
+        for roll in [10, 15, 19] {
+            ct.execute(Command::DeathSave {
+                target: merry,
+                roll,
+            });
+        }
+        assert!(ct.entities[0].death_saves.stable);
+        assert!(!ct.entities[0].is_dying());
+        assert_eq!(ct.entities[0].conditions_string(), "Stable");
+
+        // healing resets the death saves, monsters don't roll death saves
+        ct.execute(Command::Heal {
+            target: merry,
+            amount: 5,
+        });
+        assert!(ct.entities[0].death_saves.is_empty());
+        ct.execute(Command::Damage {
+            target: goblin,
+            amount: 10,
+            damage_type: None,
+            critical: false,
+        });
+        assert!(ct.entities[1].is_dead());
+        assert!(!ct.entities[1].is_dying());
+        ct.execute(Command::Stabilize { target: goblin });
+        assert!(ct.entities[1].death_saves.is_empty());
+
+        // a critical hit at 0 HP counts as two failures
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 10,
+            damage_type: None,
+            critical: false,
+        });
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 2,
+            damage_type: None,
+            critical: true,
+        });
+        assert_eq!(ct.entities[0].death_saves.failures, 2);
+        ct.execute(Command::Damage {
+            target: merry,
+            amount: 2,
+            damage_type: None,
+            critical: true,
+        });
+        assert!(ct.entities[0].is_dead());
+    }
+
+    #[test]
+    fn test_skip_policy() {
+        let mut ct = CombatTracker::new();
+        for (name, entity_type, initiative) in [
+            ("Merry", EntityType::Player, 20),
+            ("Goblin", EntityType::Monster, 15),
+            ("Orc", EntityType::Monster, 10),
+            ("Pippin", EntityType::Player, 5),
+        ] {
+            let mut entity = Entity::new(name, entity_type, 0, 12, 10);
+            entity.initiative = Some(initiative);
+            ct.add_entity(entity);
+        }
+        ct.sort_by_initiative();
+        for i in [1, 2, 3] {
+            let target = ct.entities[i].uuid;
+            ct.damage(target, 10, None, false);
+        }
+        assert_eq!(ct.skip_policy, SkipPolicy::Monsters);
+
+        // the defeated monsters are skipped, the dying player is not
+        ct.next_turn();
+        assert_eq!((ct.current_turn, ct.round), (3, 0));
+        ct.next_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 1));
+        ct.prev_turn();
+        assert_eq!((ct.current_turn, ct.round), (3, 0));
+        ct.prev_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 0));
+        ct.prev_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 0));
+
+        ct.skip_policy = SkipPolicy::Defeated;
+        ct.next_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 1));
+
+        ct.skip_policy = SkipPolicy::None;
+        ct.next_turn();
+        assert_eq!((ct.current_turn, ct.round), (1, 1));
+        ct.prev_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 1));
+
+        // everyone is skipped, the turn still moves on to the next round
+        ct.skip_policy = SkipPolicy::Defeated;
+        let merry = ct.entities[0].uuid;
+        ct.damage(merry, 10, None, false);
+        ct.next_turn();
+        assert_eq!((ct.current_turn, ct.round), (0, 2));
+
+        assert_eq!("defeated".parse(), Ok(SkipPolicy::Defeated));
+        assert!("corpses".parse::<SkipPolicy>().is_err());
+        let yaml = ct.to_yaml();
+        assert!(yaml.contains("skip_policy: Defeated"));
+        assert_eq!(
+            CombatTracker::from_yaml(yaml).skip_policy,
+            SkipPolicy::Defeated
+        );
+    }
+
+    #[test]
+    fn test_combat_log() {
+        let mut ct = CombatTracker::new();
+        let player = Entity::new("player", EntityType::Player, 0, 10, 30);
+        let monster = Entity::new("monster", EntityType::Monster, 0, 10, 20);
+        let (player_uuid, monster_uuid) = (player.uuid, monster.uuid);
+        ct.add_entity(player);
+        ct.add_entity(monster);
+
+        ct.execute(Command::Damage {
+            target: monster_uuid,
+            amount: 7,
+            damage_type: None,
+            critical: false,
+        });
+        ct.execute(Command::ToggleCondition {
+            target: monster_uuid,
+            condition: Condition::Prone,
+            duration: None,
+        });
+        ct.undo();
+
+        assert_eq!(ct.log.len(), 3);
+        assert_eq!(
+            ct.log[0],
+            LogEntry {
+                round: 0,
+                turn: 0,
+                actor: Some("player".to_string()),
+                target: Some("monster".to_string()),
+                event: CombatEvent::Damage {
+                    amount: 7,
+                    damage_type: None,
+                    adjusted_from: None,
+                    modifiers: vec![],
+                    hp_before: 20,
+                    hp_after: 13,
+                    temp_hp_absorbed: 0,
+                },
+            }
+        );
+        assert_eq!(
+            ct.log[1].event,
+            CombatEvent::ConditionAdded(Condition::Prone)
+        );
+        assert!(matches!(&ct.log[2].event, CombatEvent::Note(note) if note.starts_with("Undo")));
+
+        // changes that didn't happen aren't logged
+        ct.execute(Command::Heal {
+            target: player_uuid,
+            amount: 10,
+        });
+        ct.execute(Command::SetTempHp {
+            target: monster_uuid,
+            amount: 5,
+        });
+        ct.execute(Command::SetTempHp {
+            target: monster_uuid,
+            amount: 3,
+        });
+        assert_eq!(ct.log.len(), 4);
+        assert!(matches!(
+            ct.log[3].event,
+            CombatEvent::TempHp { amount: 5, .. }
+        ));
+
+        // the log survives a round trip through the save file
+        let loaded = CombatTracker::from_yaml(ct.to_yaml());
+        assert_eq!(loaded.log, ct.log);
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let yaml_content = "
+players:
+  - name: Arthas
+    entity_type: Player
+    initiative_modifier: 2
+    ac: 18
+    max_hp: 45
+    current_hp: 45
+    conditions: []
+
+monsters:
+  - count: 3
+    stats:
+        name: Goblin
+        entity_type: Monster
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        current_hp: 15
+        conditions: []
+  - stats:
+        name: Orc
+        entity_type: Monster
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        conditions: [Blinded, Grappled]
+        ";
+        let tracker = CombatTracker::from_yaml(yaml_content.to_string());
+
+        assert_eq!(tracker.entities.len(), 5);
+        assert!(tracker.entities.iter().any(|e| e.name == "Arthas"));
+        assert_eq!(
+            tracker
+                .entities
+                .iter()
+                .filter(|e| e.name == "Goblin")
+                .count(),
+            3
+        );
+        assert_eq!(tracker.entities.iter().map(|e| e.uuid).unique().count(), 5);
+        assert_eq!(tracker.entities.last().unwrap().current_hp, 15);
+        assert_eq!(tracker.entities.last().unwrap().conditions.len(), 2);
+    }
+
+    #[test]
+    fn test_from_yaml_death_saves() {
+        let yaml_content = "
+players:
+  - name: Samwise
+    initiative_modifier: -2
+    ac: 12
+    max_hp: 70
+  - name: Pippin
+    initiative_modifier: 2
+    ac: 16
+    max_hp: 40
+    current_hp: 0
+    death_saves:
+      failures: 2
+monsters:
+  - stats:
+        name: Orc
+        initiative_modifier: 1
+        ac: 13
+        max_hp: 15
+        current_hp: 0
+        ";
+        let tracker = CombatTracker::from_yaml(yaml_content.to_string());
+
+        // a missing current_hp means full hit points, an explicit 0 is kept
+        assert_eq!(tracker.entities[0].current_hp, 70);
+        assert_eq!(tracker.entities[1].current_hp, 0);
+        assert_eq!(tracker.entities[1].death_saves.failures, 2);
+        assert!(tracker.entities[2].is_dead());
     }
 }
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
+use std::fmt;
 
-use crate::combat::entity::{Condition, Entity, EntityType};
+use crate::combat::entity::{Condition, DeathSaves, Effect, Entity, EntityType};
 use crate::combat::tracker::CombatTracker;
 use serde::{Deserialize, Serialize};
 
@@ -11,10 +12,41 @@ pub struct PlayerClientEntity {
     pub current_hp: i32,
     pub max_hp: i32,
     pub conditions: HashSet<Condition>,
+    #[serde(default)]
+    pub effects: Vec<PlayerClientEffect>,
+    #[serde(default)]
+    pub death_saves: DeathSaves,
     pub entity_type: EntityType,
     pub initiative: i32,
 }
 
+/// What players see of an effect, its source and triggers stay with the DM
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlayerClientEffect {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<i32>,
+}
+
+impl From<&Effect> for PlayerClientEffect {
+    fn from(e: &Effect) -> Self {
+        Self {
+            name: e.name.clone(),
+            value: e.value,
+        }
+    }
+}
+
+impl fmt::Display for PlayerClientEffect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(value) = self.value {
+            write!(f, " {value}")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct PlayerClientState {
     pub round: usize,
@@ -29,6 +61,8 @@ impl From<&Entity> for PlayerClientEntity {
             current_hp: e.current_hp,
             max_hp: e.max_hp,
             conditions: e.conditions.iter().cloned().collect(),
+            effects: e.effects.iter().map(PlayerClientEffect::from).collect(),
+            death_saves: e.death_saves.clone(),
             id: e.id,
             entity_type: e.entity_type.clone(),
             initiative: e.initiative.expect("Initiative missing!"),
@@ -36,12 +70,27 @@ impl From<&Entity> for PlayerClientEntity {
     }
 }
 
+/// The lair step is left out, during its turn `current_turn` points past the last entity.
 impl From<&CombatTracker> for PlayerClientState {
     fn from(t: &CombatTracker) -> Self {
+        let is_lair = |e: &&Entity| e.entity_type == EntityType::Lair;
+        let entities: Vec<PlayerClientEntity> = t
+            .entities
+            .iter()
+            .filter(|e| !is_lair(e))
+            .map(PlayerClientEntity::from)
+            .collect();
+        let current_turn = match t.entities.get(t.current_turn) {
+            Some(current) if is_lair(&current) => entities.len(),
+            _ => t.entities[..t.current_turn.min(t.entities.len())]
+                .iter()
+                .filter(|e| !is_lair(e))
+                .count(),
+        };
         Self {
             round: t.round,
-            current_turn: t.current_turn,
-            entities: t.entities.iter().map(PlayerClientEntity::from).collect(),
+            current_turn,
+            entities,
         }
     }
 }
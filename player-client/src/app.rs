@@ -1,5 +1,4 @@
 use core::dto::PlayerClientState;
-use std::sync::Arc;
 
 use color_eyre::eyre::Context;
 use color_eyre::Result;
@@ -1,7 +1,7 @@
 use clap::Parser;
 use color_eyre::Result;
 use env_logger::{Builder, Target};
-use log::{debug, info};
+use log::info;
 use std::fs::File;
 use std::io::Write;
 
@@ -1,4 +1,4 @@
-use core::combat::entity;
+use core::combat::entity::EntityType;
 use core::dto::PlayerClientEntity;
 
 use color_eyre::Result;
@@ -59,11 +59,30 @@ pub fn get_ref_array_string(entity: &PlayerClientEntity) -> Vec<String> {
     vec![
         entity.initiative.to_string(),
         display_name,
-        format!("{}", get_health_state(entity.max_hp, entity.current_hp)),
-        entity.conditions.iter().sorted().join(", "),
+        get_health_string(entity),
+        entity
+            .conditions
+            .iter()
+            .sorted()
+            .map(|c| c.to_string())
+            .chain(entity.effects.iter().map(|e| e.to_string()))
+            .join(", "),
     ]
 }
 
+/// Player characters at 0 HP show their death saves instead of being dead
+fn get_health_string(entity: &PlayerClientEntity) -> String {
+    if entity.entity_type != EntityType::Player || entity.current_hp > 0 {
+        get_health_state(entity.max_hp, entity.current_hp).to_string()
+    } else if entity.death_saves.failures >= 3 {
+        "Dead".to_string()
+    } else if entity.death_saves.stable {
+        "Stable".to_string()
+    } else {
+        format!("Dying\n{}", entity.death_saves)
+    }
+}
+
 fn draw_table(frame: &mut Frame, app: &mut App, area: Rect) -> Result<()> {
     let header_style = Style::default().bg(app.colors.header_bg);
     let selected_row_style = Style::default()
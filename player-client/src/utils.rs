@@ -25,7 +25,7 @@ pub fn get_health_state(max_hp: i32, current_hp: i32) -> HealthState {
     match ratio {
         p if p >= 0.6666 => HealthState::Healthy,
         p if p >= 0.3333 => HealthState::Wounded,
-        p if p == 0.0 => HealthState::Dead,
+        0.0 => HealthState::Dead,
         _ => HealthState::NearDeath,
     }
 }
@@ -5,10 +5,10 @@ use log::info;
 use std::io::Write;
 use std::{fs::File, sync::Arc};
 use tokio::sync::Mutex;
-use tui::utils::{load_combat_yaml, validate_yaml_extension};
+use tui::utils::{load_bestiary, load_combat_yaml, validate_yaml_extension};
 
 use clap::Parser;
-use color_eyre::Result;
+use color_eyre::{eyre::Context, Result};
 use env_logger::{Builder, Target};
 
 mod tui;
@@ -47,7 +47,10 @@ async fn main() -> Result<()> {
         &combat_yaml_string
     );
 
-    let tracker = Arc::new(Mutex::new(CombatTracker::from_yaml(combat_yaml_string)));
+    let bestiary = load_bestiary(&args)?;
+    let tracker = CombatTracker::from_yaml_with_bestiary(combat_yaml_string, &bestiary)
+        .wrap_err_with(|| format!("Failed to load '{}'", args.combat_file.display()))?;
+    let tracker = Arc::new(Mutex::new(tracker));
 
     // Start server
     let server_tracker = Arc::clone(&tracker);
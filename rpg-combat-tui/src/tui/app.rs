@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     fs::{self},
     path::PathBuf,
     sync::Arc,
@@ -8,12 +9,24 @@ use color_eyre::{
     eyre::{Context, ContextCompat},
     Result,
 };
-use core::combat::{entity::Condition, tracker::CombatTracker};
+use core::combat::{
+    command::Command,
+    damage::{split_damage_type, DamageType},
+    dice::{DiceError, DiceExpr},
+    entity::{Condition, ConditionDuration, Effect, Entity, EntityType, Trigger},
+    stats::Ability,
+    tracker::{CombatTracker, ConcentrationCheck},
+};
 use crossterm::event::{self, Event, KeyEvent, KeyEventKind};
+use itertools::Itertools;
 use log::{debug, info};
-use ratatui::widgets::TableState;
+use ratatui::{
+    style::{Modifier, Style},
+    widgets::TableState,
+};
 use tokio::sync::Mutex;
-use tui_textarea::{Input, Key, TextArea};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
+use uuid::Uuid;
 
 use super::{
     cli::Args,
@@ -21,13 +34,15 @@ use super::{
     ui::{self, TableColors},
 };
 
+type PopupAction<'t> = Box<dyn FnMut(&mut App<'t>, String) + Send>;
+
 #[derive(Default)]
 pub struct Popup<'t> {
     pub active: bool,
     pub prompt: String,
     pub input: TextArea<'t>,
     pub show_input: bool,
-    pub confirm_action: Option<Box<dyn FnMut(&mut App<'t>, String) + Send>>,
+    pub confirm_action: Option<PopupAction<'t>>,
     pub size: (u16, u16),
 }
 
@@ -67,13 +82,101 @@ impl<'t> Popup<'t> {
     }
 }
 
+type FormAction<'t> = Box<dyn FnMut(&mut App<'t>, Vec<String>) + Send>;
+
+/// A popup with several labeled inputs, <Tab> moves between them
+#[derive(Default)]
+pub struct Form<'t> {
+    pub active: bool,
+    pub title: String,
+    pub labels: Vec<String>,
+    pub inputs: Vec<TextArea<'t>>,
+    pub focus: usize,
+    /// Shown below the inputs, e.g. why the last submit was rejected
+    pub error: Option<String>,
+    pub confirm_action: Option<FormAction<'t>>,
+}
+
+impl<'t> Form<'t> {
+    /// Shows the form with the given `(label, value)` fields
+    pub fn show<F: FnMut(&mut App<'t>, Vec<String>) + Send + 'static>(
+        &mut self,
+        title: &str,
+        fields: &[(&str, &str)],
+        error: Option<String>,
+        action: F,
+    ) {
+        self.active = true;
+        self.title = title.to_string();
+        self.labels = fields.iter().map(|(label, _)| label.to_string()).collect();
+        self.inputs = fields
+            .iter()
+            .map(|(_, value)| {
+                let mut input = TextArea::from([*value]);
+                input.move_cursor(CursorMove::End);
+                input
+            })
+            .collect();
+        self.error = error;
+        self.confirm_action = Some(Box::new(action));
+        self.set_focus(0);
+    }
+
+    pub fn hide(&mut self) {
+        self.active = false;
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        self.inputs.iter().map(|i| i.lines().join("")).collect()
+    }
+
+    pub fn focus_next(&mut self) {
+        self.set_focus((self.focus + 1) % self.inputs.len().max(1));
+    }
+
+    pub fn focus_prev(&mut self) {
+        let len = self.inputs.len().max(1);
+        self.set_focus((self.focus + len - 1) % len);
+    }
+
+    /// Only the focused input shows a cursor
+    fn set_focus(&mut self, focus: usize) {
+        self.focus = focus;
+        for (i, input) in self.inputs.iter_mut().enumerate() {
+            input.set_cursor_style(if i == focus {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            });
+        }
+    }
+}
+
+/// The kind of hit point change requested from the popup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HpChange {
+    Damage,
+    Heal,
+    TempHp,
+}
+
 pub struct App<'t> {
     pub exit: bool,
     pub tracker: Arc<Mutex<CombatTracker>>,
     pub state: TableState,
     pub popup: Popup<'t>,
+    pub form: Form<'t>,
     pub colors: TableColors,
     pub output_file: PathBuf,
+    pub show_log: bool,
+    /// Shows the stat block of the selected entity below the table
+    pub show_details: bool,
+    /// Shows all key bindings, the next key closes it again
+    pub show_help: bool,
+    /// Number of log lines scrolled up from the latest entry
+    pub log_scroll: usize,
+    /// Entities marked with `m` for group actions like damage or saving throws
+    pub marked: HashSet<Uuid>,
 }
 
 impl App<'_> {
@@ -98,6 +201,9 @@ impl App<'_> {
                     .wrap_err("Something wrong with filename")?,
         );
         info!("Using {} as a save file.", &save_file.display());
+        if let Some(skip_policy) = args.skip {
+            tracker.blocking_lock().skip_policy = skip_policy;
+        }
         tracker.blocking_lock().roll_initiative(true, false);
 
         Ok(Self {
@@ -105,8 +211,14 @@ impl App<'_> {
             tracker,
             state: TableState::default().with_selected(0),
             popup: Popup::new(),
+            form: Form::default(),
             colors: TableColors::new(),
             output_file: save_file,
+            show_log: true,
+            show_details: true,
+            show_help: false,
+            log_scroll: 0,
+            marked: HashSet::new(),
         })
     }
 
@@ -124,7 +236,10 @@ impl App<'_> {
     fn handle_events(&mut self) -> Result<()> {
         match event::read()? {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                let res: Result<()> = if self.popup.active {
+                let res: Result<()> = if self.form.active {
+                    self.handle_form_key_event(key_event)
+                        .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}"))
+                } else if self.popup.active {
                     self.handle_popup_key_event(key_event)
                         .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}"))
                 } else {
@@ -144,10 +259,11 @@ impl App<'_> {
             Input {
                 key: Key::Enter, ..
             } => {
+                // hide first so the action can open a follow-up popup
+                self.popup.hide();
                 if let Some(mut action) = self.popup.confirm_action.take() {
                     action(self, self.popup.input.lines().join(""));
                 }
-                self.popup.hide();
             }
             input => {
                 self.popup.input.input(input);
@@ -156,7 +272,40 @@ impl App<'_> {
         Ok(())
     }
 
+    fn handle_form_key_event(&mut self, key_event: KeyEvent) -> Result<()> {
+        // <Shift>+<Tab> isn't converted into a tui_textarea key
+        if key_event.code == event::KeyCode::BackTab {
+            self.form.focus_prev();
+            return Ok(());
+        }
+        match key_event.into() {
+            Input { key: Key::Esc, .. } => self.form.hide(),
+            Input {
+                key: Key::Enter, ..
+            } => {
+                // hide first so the action can show the form again
+                self.form.hide();
+                if let Some(mut action) = self.form.confirm_action.take() {
+                    action(self, self.form.values());
+                }
+            }
+            Input { key: Key::Tab, .. } | Input { key: Key::Down, .. } => self.form.focus_next(),
+            Input { key: Key::Up, .. } => self.form.focus_prev(),
+            input => {
+                let focus = self.form.focus;
+                if let Some(field) = self.form.inputs.get_mut(focus) {
+                    field.input(input);
+                }
+            }
+        };
+        Ok(())
+    }
+
     fn handle_key_event(&mut self, key_event: event::KeyEvent) -> Result<()> {
+        if self.show_help {
+            self.show_help = false;
+            return Ok(());
+        }
         match key_event.into() {
             Input { key: Key::Esc, .. }
             | Input {
@@ -173,14 +322,14 @@ impl App<'_> {
                 key: Key::Char(' '),
                 ..
             } => {
-                self.tracker.blocking_lock().next_turn();
+                self.next_turn();
             }
             Input { key: Key::Left, .. }
             | Input {
                 key: Key::Backspace,
                 ..
             } => {
-                self.tracker.blocking_lock().prev_turn();
+                self.execute(Command::PrevTurn);
             }
             Input { key: Key::Down, .. } => {
                 self.state.select_next();
@@ -192,13 +341,19 @@ impl App<'_> {
                 key: Key::Char('d'),
                 ..
             } => {
-                self.damage_heal(false);
+                self.change_hp(HpChange::Damage);
             }
             Input {
                 key: Key::Char('h'),
                 ..
             } => {
-                self.damage_heal(true);
+                self.change_hp(HpChange::Heal);
+            }
+            Input {
+                key: Key::Char('t'),
+                ..
+            } => {
+                self.change_hp(HpChange::TempHp);
             }
             Input {
                 key: Key::Char('i'),
@@ -211,14 +366,17 @@ impl App<'_> {
                 ctrl: true,
                 ..
             } => {
-                self.tracker.blocking_lock().roll_initiative(true, true);
+                self.execute(Command::RollInitiative {
+                    group_by_name: true,
+                    re_roll: true,
+                });
             }
             Input {
                 key: Key::Char('c'),
                 ctrl: true,
                 ..
             } => {
-                self.tracker.blocking_lock().reset_combat();
+                self.execute(Command::ResetCombat);
             }
             Input {
                 key: Key::Char('c'),
@@ -226,148 +384,1592 @@ impl App<'_> {
             } => {
                 self.change_conditions();
             }
+            Input {
+                key: Key::Char('e'),
+                ..
+            } => {
+                self.change_effects();
+            }
+            Input {
+                key: Key::Char('C'),
+                ..
+            } => {
+                self.change_concentration();
+            }
+            Input {
+                key: Key::Char('a'),
+                ctrl: true,
+                ..
+            } => {
+                self.toggle_mark_monsters();
+            }
+            Input {
+                key: Key::Char('a'),
+                ..
+            } => {
+                self.add_entities(None, None);
+            }
+            Input {
+                key: Key::Char('w'),
+                ..
+            } => {
+                self.execute(Command::DelayTurn);
+                self.start_turn();
+            }
+            Input {
+                key: Key::Char('W'),
+                ..
+            } => {
+                self.resume_turn();
+            }
+            Input {
+                key: Key::Char('R'),
+                ..
+            } => {
+                self.ready_action();
+            }
+            Input {
+                key: Key::Char('L'),
+                ..
+            } => {
+                self.use_legendary_action();
+            }
+            Input {
+                key: Key::Char('T'),
+                ..
+            } => {
+                self.change_triggers();
+            }
+            Input {
+                key: Key::Char('A'),
+                ..
+            } => {
+                self.use_ability();
+            }
+            Input {
+                key: Key::Char('m'),
+                ..
+            } => {
+                self.toggle_mark();
+            }
+            Input {
+                key: Key::Char('M'),
+                ..
+            } => {
+                self.marked.clear();
+            }
+            Input {
+                key: Key::Char('g'),
+                ..
+            } => {
+                self.toggle_mark_group();
+            }
+            Input {
+                key: Key::Char('s'),
+                ..
+            } => {
+                let targets = self.targets();
+                self.group_save(targets, None, None, None);
+            }
+            Input {
+                key: Key::Char('k'),
+                ..
+            } => {
+                self.attack(None, None);
+            }
+            Input {
+                key: Key::Char(digit @ '1'..='9'),
+                ..
+            } => {
+                let index = digit.to_digit(10).unwrap_or(1) as usize - 1;
+                self.use_action(index);
+            }
+            Input {
+                key: Key::Char('x'),
+                ..
+            }
+            | Input {
+                key: Key::Delete, ..
+            } => {
+                self.remove_entity();
+            }
+            Input {
+                key: Key::Char('l'),
+                ..
+            } => {
+                self.show_log = !self.show_log;
+            }
+            Input {
+                key: Key::Char('v'),
+                ..
+            } => {
+                self.show_details = !self.show_details;
+            }
+            Input {
+                key: Key::Char('?'),
+                ..
+            } => {
+                self.show_help = true;
+            }
+            Input {
+                key: Key::PageUp, ..
+            } => {
+                let log_len = self.tracker.blocking_lock().log.len();
+                self.log_scroll = (self.log_scroll + 5).min(log_len.saturating_sub(1));
+            }
+            Input {
+                key: Key::PageDown, ..
+            } => {
+                self.log_scroll = self.log_scroll.saturating_sub(5);
+            }
+            Input {
+                key: Key::Char('u'),
+                ..
+            } => {
+                self.undo();
+            }
+            Input {
+                key: Key::Char('U'),
+                ..
+            } => {
+                self.redo();
+            }
             _text_input => {}
         }
         Ok(())
     }
 
-    fn damage_heal(&mut self, heal: bool) {
-        let prompt = if heal {
-            "Enter heal amount:"
-        } else {
-            "Enter damage amount:"
-        };
-        if let Some(selected) = self.state.selected() {
-            self.popup
-                .show(prompt, true, (30, 20), move |app, input_amount| {
-                    if let Ok(amount) = input_amount.parse::<i32>() {
-                        if let Some(entity) = app.tracker.blocking_lock().entities.get_mut(selected)
-                        {
-                            entity.current_hp = if heal {
-                                (entity.current_hp + amount).max(0).min(entity.max_hp)
-                            } else {
-                                (entity.current_hp - amount).max(0).min(entity.max_hp)
-                            }
-                        }
-                    }
-                });
+    /// Returns the uuid of the entity selected in the table
+    fn selected_uuid(&self) -> Option<Uuid> {
+        let selected = self.state.selected()?;
+        self.tracker
+            .blocking_lock()
+            .entities
+            .get(selected)
+            .map(|e| e.uuid)
+    }
+
+    fn select_uuid(&mut self, uuid: Uuid) {
+        let index = self
+            .tracker
+            .blocking_lock()
+            .entities
+            .iter()
+            .position(|e| e.uuid == uuid);
+        if index.is_some() {
+            self.state.select(index);
         }
     }
 
-    fn set_initiative(&mut self) {
-        let prompt = "Enter Initiative:";
-        if let Some(selected) = self.state.selected() {
-            self.popup
-                .show(prompt, true, (30, 20), move |app, input_amount| {
-                    if let Ok(new_ini) = input_amount.parse::<i32>() {
-                        let mut tracker = app.tracker.blocking_lock();
-                        if let Some(entity) = tracker.entities.get_mut(selected) {
-                            info!(
-                                "Setting initiative of entity {} ({}) to {}",
-                                entity.name, entity.id, &new_ini
-                            );
-                            entity.initiative = Some(new_ini);
-                            tracker.sort_by_initiative();
-                        }
-                    }
-                });
+    /// Marks or unmarks the selected entity and moves on to the next row
+    fn toggle_mark(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
         };
+        if !self.marked.remove(&target) {
+            self.marked.insert(target);
+        }
+        self.state.select_next();
     }
 
-    fn change_conditions(&mut self) {
-        let selected = match self.state.selected() {
-            Some(s) => s,
-            None => return,
+    /// Marks all entities with the same name as the selected one, e.g. all goblins.
+    /// Unmarks them if they are all marked already.
+    fn toggle_mark_group(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
         };
-
-        let tracker = self.tracker.blocking_lock();
-        let entity = match tracker.entities.get(selected) {
-            Some(e) => e,
-            None => return,
+        let group: Vec<Uuid> = {
+            let tracker = self.tracker.blocking_lock();
+            let Some(name) = tracker.get_entity_by_uuid(target).map(|e| e.name.clone()) else {
+                return;
+            };
+            tracker
+                .entities
+                .iter()
+                .filter(|e| e.name == name)
+                .map(|e| e.uuid)
+                .collect()
         };
+        self.toggle_marks(group);
+    }
 
-        let all_conditions: Vec<Condition> = vec![
-            Condition::Blinded,
-            Condition::Charmed,
-            Condition::Deafened,
-            Condition::Frightened,
-            Condition::Grappled,
-            Condition::Incapacitated,
-            Condition::Invisible,
-            Condition::Paralyzed,
-            Condition::Petrified,
-            Condition::Poisoned,
-            Condition::Prone,
-            Condition::Restrained,
-            Condition::Stunned,
-            Condition::Unconscious,
-        ];
+    /// Marks all monsters that are still standing, or unmarks them if they are all marked
+    fn toggle_mark_monsters(&mut self) {
+        let monsters: Vec<Uuid> = self
+            .tracker
+            .blocking_lock()
+            .entities
+            .iter()
+            .filter(|e| e.entity_type == EntityType::Monster && e.current_hp > 0)
+            .map(|e| e.uuid)
+            .collect();
+        self.toggle_marks(monsters);
+    }
 
-        let prompt_text = all_conditions
+    fn toggle_marks(&mut self, uuids: Vec<Uuid>) {
+        if uuids.iter().all(|uuid| self.marked.contains(uuid)) {
+            uuids.iter().for_each(|uuid| {
+                self.marked.remove(uuid);
+            });
+        } else {
+            self.marked.extend(uuids);
+        }
+    }
+
+    /// Returns the marked entities in initiative order, or the selected one if none are marked
+    fn targets(&self) -> Vec<Uuid> {
+        let marked: Vec<Uuid> = self
+            .tracker
+            .blocking_lock()
+            .entities
             .iter()
-            .enumerate()
-            .map(|(i, condition)| {
-                let marker = if entity.conditions.contains(condition) {
-                    "[X]"
-                } else {
-                    "[ ]"
-                };
-                format!("{} {} - {}", marker, i + 1, condition)
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
+            .map(|e| e.uuid)
+            .filter(|uuid| self.marked.contains(uuid))
+            .collect();
+        if marked.is_empty() {
+            self.selected_uuid().into_iter().collect()
+        } else {
+            marked
+        }
+    }
 
-        self.popup.show(
-            &format!("Select condition to toggle:\n\n{}", prompt_text),
-            true,
-            (10, 30),
-            move |app, input| {
-                if let Ok(index) = input.parse::<usize>() {
-                    if let Some(entity) = app.tracker.blocking_lock().entities.get_mut(selected) {
-                        if index > 0 && index <= all_conditions.len() {
-                            let condition = &all_conditions[index - 1];
-                            if entity.conditions.contains(condition) {
-                                entity.conditions.remove(condition);
-                            } else {
-                                entity.conditions.insert(condition.clone());
-                            }
-                        }
-                    }
-                }
-            },
-        );
+    fn execute(&mut self, command: Command) {
+        self.tracker.blocking_lock().execute(command);
+        if !self.popup.active {
+            self.check_concentration();
+        }
     }
 
-    fn exit(&mut self) {
-        info!("Application stopped.");
-        self.exit = true;
+    /// Asks for the concentration save of the next entity that took damage while concentrating
+    fn check_concentration(&mut self) {
+        let Some(check) = self.tracker.blocking_lock().next_concentration_check() else {
+            return;
+        };
+        self.concentration_popup(check, None);
     }
 
-    fn confirm_close(&mut self) {
-        self.popup.show(
-            "Do you want to close the application?",
-            false,
-            (30, 20),
-            |app, _| app.exit(),
+    /// Shows the prompt for a concentration save, `error` keeps an invalid input and its message
+    fn concentration_popup(&mut self, check: ConcentrationCheck, error: Option<(String, String)>) {
+        let tracker = self.tracker.blocking_lock();
+        let Some(entity) = tracker.get_entity_by_uuid(check.target) else {
+            return;
+        };
+        let modifier = match entity.save_modifier(Ability::Con) {
+            Some(modifier) => format!("{modifier:+}"),
+            None => "+0, no stored modifier".to_string(),
+        };
+        let mut prompt = format!(
+            "{} is concentrating on {}.\n\nCON save DC {}\n\nEnter the rolled total or leave empty to auto-roll ({})",
+            entity.display_name(),
+            check.spell,
+            check.dc,
+            modifier
         );
+        drop(tracker);
+        if let Some((_, message)) = &error {
+            prompt = format!("{prompt}\n\nInvalid input: {message}");
+        }
+        self.popup.show(&prompt, true, (30, 30), move |app, input| {
+            let roll = match input.trim() {
+                "" => None,
+                total => match total.parse::<i32>() {
+                    Ok(total) => Some(total),
+                    Err(e) => {
+                        app.concentration_popup(check.clone(), Some((input, e.to_string())));
+                        return;
+                    }
+                },
+            };
+            let result = app.tracker.blocking_lock().saving_throw(
+                check.target,
+                Ability::Con,
+                check.dc,
+                roll,
+            );
+            let Some((roll, success)) = result else {
+                return;
+            };
+            let message = if success {
+                format!(
+                    "Rolled {roll} vs DC {}: concentration on {} holds",
+                    check.dc, check.spell
+                )
+            } else {
+                app.tracker
+                    .blocking_lock()
+                    .execute(Command::EndConcentration {
+                        target: check.target,
+                    });
+                format!(
+                    "Rolled {roll} vs DC {}: concentration on {} is lost",
+                    check.dc, check.spell
+                )
+            };
+            app.popup.show(&message, false, (30, 20), |app, _| {
+                app.check_concentration()
+            });
+        });
+        if let Some((input, _)) = error {
+            self.popup.input.insert_str(input);
+        }
     }
 
-    fn backup(&mut self) -> Result<()> {
-        debug!("Writing state to file {}", &self.output_file.display());
-        fs::write(&self.output_file, self.tracker.blocking_lock().to_yaml())?;
-        Ok(())
+    fn next_turn(&mut self) {
+        self.execute(Command::NextTurn);
+        self.start_turn();
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::tui::utils::load_combat_yaml;
 
-    use super::*;
+    /// Shows what needs to be done at the start of the new current entity's turn.
+    /// Every trigger popup comes back here until all triggers were shown.
+    fn start_turn(&mut self) {
+        if self.check_trigger() {
+            return;
+        }
+        self.check_concentration();
+        if !self.popup.active {
+            self.check_lair();
+        }
+        if !self.popup.active {
+            self.check_death_save(None);
+        }
+    }
 
-    use std::env::temp_dir;
-    use std::fs::File;
-    use std::io::Write;
+    /// Shows the next start or end of turn trigger, heal and damage are applied right away.
+    /// Returns false if there was none.
+    fn check_trigger(&mut self) -> bool {
+        let mut tracker = self.tracker.blocking_lock();
+        let Some(pending) = tracker.next_trigger() else {
+            return false;
+        };
+        let name = tracker
+            .get_entity_by_uuid(pending.target)
+            .map(|e| e.display_name())
+            .unwrap_or_default();
+        let applied = tracker.apply_trigger(&pending);
+        drop(tracker);
+        let prompt = format!("{name}, {pending}");
+        match applied {
+            Ok(Some(result)) if result.applied => {
+                let prompt = format!(
+                    "{prompt}\n\nRolled {}, applied to {name}.\n\nEnter 'u' to undo it:",
+                    result.roll
+                );
+                self.popup.show(&prompt, true, (30, 30), |app, input| {
+                    if input.trim().eq_ignore_ascii_case("u") {
+                        app.undo();
+                    }
+                    app.start_turn();
+                });
+            }
+            Ok(Some(result)) => self.popup.show(
+                &format!("{prompt}\n\nRolled {}, no change to {name}.", result.roll),
+                false,
+                (30, 30),
+                |app, _| app.start_turn(),
+            ),
+            Ok(None) => self
+                .popup
+                .show(&prompt, false, (30, 20), |app, _| app.start_turn()),
+            Err(error) => self.popup.show(
+                &format!("{prompt}\n\nCould not roll it: {error}"),
+                false,
+                (30, 20),
+                |app, _| app.start_turn(),
+            ),
+        }
+        true
+    }
+
+    /// Adds a start or end of turn trigger to the selected entity or removes one by its number
+    fn change_triggers(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let Some(entity) = self
+            .tracker
+            .blocking_lock()
+            .get_entity_by_uuid(target)
+            .cloned()
+        else {
+            return;
+        };
+        let triggers = if entity.triggers.is_empty() {
+            "No triggers".to_string()
+        } else {
+            entity
+                .triggers
+                .iter()
+                .enumerate()
+                .map(|(i, trigger)| format!("{}: {trigger}", i + 1))
+                .join("\n")
+        };
+        let prompt = format!(
+            "Triggers of {}\n\n{triggers}\n\nEnter 'start' or 'end' and a reminder, optionally followed by e.g. 'heal 10' or 'damage 2d6 fire', or the number of a trigger to remove it:",
+            entity.display_name()
+        );
+        self.popup.show(&prompt, true, (40, 40), move |app, input| {
+            let input = input.trim();
+            if input.is_empty() {
+                return;
+            }
+            if let Ok(number) = input.parse::<usize>() {
+                if let Some(index) = number.checked_sub(1) {
+                    app.execute(Command::RemoveTrigger { target, index });
+                }
+                return;
+            }
+            match input.parse::<Trigger>() {
+                Ok(trigger) => app.execute(Command::AddTrigger { target, trigger }),
+                Err(error) => app
+                    .popup
+                    .show(&error, false, (30, 20), |app, _| app.change_triggers()),
+            }
+        });
+    }
+
+    /// Lists the lair actions when the lair step comes up
+    fn check_lair(&mut self) {
+        let tracker = self.tracker.blocking_lock();
+        if tracker
+            .get_current_entity()
+            .is_none_or(|e| e.entity_type != EntityType::Lair)
+        {
+            return;
+        }
+        let actions = tracker
+            .lair_actions()
+            .iter()
+            .map(|(name, action)| format!("{name}: {action}"))
+            .join("\n");
+        drop(tracker);
+        self.popup.show(
+            &format!("Lair actions on initiative count 20\n\n{actions}"),
+            false,
+            (40, 30),
+            |_, _| {},
+        );
+    }
+
+    /// Spends legendary actions of the selected entity
+    fn use_legendary_action(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let Some(entity) = self
+            .tracker
+            .blocking_lock()
+            .get_entity_by_uuid(target)
+            .filter(|e| e.legendary_actions > 0)
+            .cloned()
+        else {
+            return;
+        };
+        let prompt = format!(
+            "{} has {}/{} legendary actions left.\n\nEnter the cost or leave empty for 1:",
+            entity.display_name(),
+            entity.legendary_actions_remaining(),
+            entity.legendary_actions
+        );
+        self.popup.show(&prompt, true, (30, 20), move |app, input| {
+            let cost = match input.trim() {
+                "" => Some(1),
+                cost => cost.parse::<u32>().ok(),
+            };
+            if let Some(cost) = cost {
+                app.execute(Command::UseLegendaryAction { target, cost });
+            }
+        });
+    }
+
+    /// Uses a recharge or per-day ability of the selected entity, a spent one is restored
+    fn use_ability(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let Some(entity) = self
+            .tracker
+            .blocking_lock()
+            .get_entity_by_uuid(target)
+            .filter(|e| !e.limited_abilities.is_empty())
+            .cloned()
+        else {
+            return;
+        };
+        let abilities = entity
+            .limited_abilities
+            .iter()
+            .enumerate()
+            .map(|(i, ability)| match ability.recharge {
+                Some(min) if min < 6 => format!("{}: {ability} (Recharge {min}-6)", i + 1),
+                Some(_) => format!("{}: {ability} (Recharge 6)", i + 1),
+                None => format!("{}: {ability}", i + 1),
+            })
+            .join("\n");
+        let prompt = format!(
+            "Abilities of {}\n\n{abilities}\n\nEnter the number of an ability to use it, a spent one is restored:",
+            entity.display_name()
+        );
+        self.popup.show(&prompt, true, (40, 30), move |app, input| {
+            let Some(ability) = input
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| entity.limited_abilities.get(number.checked_sub(1)?))
+            else {
+                return;
+            };
+            let name = ability.name.clone();
+            if ability.is_available() {
+                app.execute(Command::UseAbility { target, name });
+            } else {
+                app.execute(Command::RestoreAbility { target, name });
+            }
+        });
+    }
+
+    /// Shows the form for a saving throw of the `targets`, e.g. against a Fireball.
+    /// `values` keeps the input after an invalid submit, `uses` is executed with the damage.
+    fn group_save(
+        &mut self,
+        targets: Vec<Uuid>,
+        values: Option<Vec<String>>,
+        error: Option<String>,
+        uses: Option<Command>,
+    ) {
+        if targets.is_empty() {
+            return;
+        }
+        let values = values.unwrap_or_else(|| ["DEX", "", ""].map(String::from).to_vec());
+        let fields: Vec<(&str, &str)> = GROUP_SAVE_FIELDS
+            .iter()
+            .zip(values.iter())
+            .map(|(label, value)| (*label, value.as_str()))
+            .collect();
+        let title = match targets.len() {
+            1 => "Saving Throw".to_string(),
+            n => format!("Saving Throw of {n} Combatants"),
+        };
+        self.form.show(&title, &fields, error, move |app, values| {
+            let rolled = parse_group_save(&values).and_then(|save| {
+                app.roll_group_save(&targets, save, uses.clone())
+                    .map_err(|error| error.to_string())
+            });
+            if let Err(error) = rolled {
+                app.group_save(targets.clone(), Some(values), Some(error), uses.clone());
+            }
+        });
+    }
+
+    /// Rolls the saves and the damage once for all targets and shows the results
+    fn roll_group_save(
+        &mut self,
+        targets: &[Uuid],
+        save: GroupSave,
+        uses: Option<Command>,
+    ) -> Result<(), DiceError> {
+        let message = {
+            let mut tracker = self.tracker.blocking_lock();
+            let mut lines = vec![format!("{} save DC {}", save.ability, save.dc)];
+            let (damage, damage_type) = match &save.damage {
+                Some((expr, damage_type)) => {
+                    let result = tracker.roll(expr)?;
+                    lines.push(match damage_type {
+                        Some(damage_type) => format!("{damage_type} damage: {result}"),
+                        None => format!("Damage: {result}"),
+                    });
+                    (result.total.max(0), *damage_type)
+                }
+                None => (0, None),
+            };
+            lines.push(String::new());
+            let results =
+                tracker.group_save(targets, save.ability, save.dc, damage, damage_type, uses);
+            for result in results {
+                let Some(entity) = tracker.get_entity_by_uuid(result.target) else {
+                    continue;
+                };
+                let outcome = if result.success { "succeeds" } else { "fails" };
+                let mut line = format!("{}: {} {outcome}", entity.display_name(), result.roll);
+                if save.damage.is_some() {
+                    line += &format!(
+                        ", takes {}, HP {}",
+                        result.damage,
+                        entity.ref_array_string()[2]
+                    );
+                }
+                lines.push(line);
+            }
+            lines.join("\n")
+        };
+        self.popup.show(&message, false, (40, 40), |app, _| {
+            app.check_concentration()
+        });
+        Ok(())
+    }
+
+    /// Shows the form for an attack on the selected entity, by default by the one whose turn it is.
+    /// `values` keeps the input after an invalid submit.
+    fn attack(&mut self, values: Option<Vec<String>>, error: Option<String>) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let tracker = self.tracker.blocking_lock();
+        let Some(name) = tracker.get_entity_by_uuid(target).map(|e| e.display_name()) else {
+            return;
+        };
+        let attacker = tracker
+            .get_current_entity()
+            .map(|e| e.display_name())
+            .unwrap_or_default();
+        drop(tracker);
+        let values =
+            values.unwrap_or_else(|| vec![attacker, String::new(), String::new(), String::new()]);
+        let fields: Vec<(&str, &str)> = ATTACK_FIELDS
+            .iter()
+            .zip(values.iter())
+            .map(|(label, value)| (*label, value.as_str()))
+            .collect();
+        self.form.show(
+            &format!("Attack {name}"),
+            &fields,
+            error,
+            move |app, values| {
+                let attack = parse_attack(&values).and_then(|attack| {
+                    let tracker = app.tracker.blocking_lock();
+                    let attacker = tracker
+                        .entities
+                        .iter()
+                        .find(|e| e.display_name().eq_ignore_ascii_case(&attack.attacker))
+                        .map(|e| e.uuid)
+                        .ok_or_else(|| format!("Unknown attacker '{}'", attack.attacker))?;
+                    Ok((attacker, attack))
+                });
+                let rolled = attack.and_then(|(attacker, attack)| {
+                    app.roll_attack(attacker, target, attack, None)
+                        .map_err(|error| error.to_string())
+                });
+                if let Err(error) = rolled {
+                    app.attack(Some(values), Some(error));
+                }
+            },
+        );
+    }
+
+    /// Rolls a stored action of the entity whose turn it is. Attacks target the selected entity,
+    /// actions with a save DC open the saving throw form for the marked ones.
+    /// An ability of the same name, like a breath weapon, is used up once the action is rolled,
+    /// in the same undo step as its damage.
+    fn use_action(&mut self, index: usize) {
+        let tracker = self.tracker.blocking_lock();
+        let Some(entity) = tracker.get_current_entity() else {
+            return;
+        };
+        let Some(action) = entity.actions.get(index).cloned() else {
+            return;
+        };
+        let (attacker, attacker_name) = (entity.uuid, entity.display_name());
+        let ability = entity
+            .limited_abilities
+            .iter()
+            .find(|ability| ability.name.eq_ignore_ascii_case(&action.name))
+            .cloned();
+        drop(tracker);
+        if ability
+            .as_ref()
+            .is_some_and(|ability| !ability.is_available())
+        {
+            let message = format!("{} of {attacker_name} isn't available", action.name);
+            self.popup.show(&message, false, (30, 20), |_, _| {});
+            return;
+        }
+
+        let uses = ability.map(|ability| Command::UseAbility {
+            target: attacker,
+            name: ability.name,
+        });
+        let damage = action.damage_string().unwrap_or_default();
+        if let Some(bonus) = action.to_hit {
+            let target = self.selected_uuid().filter(|target| *target != attacker);
+            let Some(target) = target else {
+                let message = format!("Select the target of {} first", action.name);
+                self.popup.show(&message, false, (30, 20), |_, _| {});
+                return;
+            };
+            let damage = match parse_damage(&damage) {
+                Ok(damage) => damage,
+                Err(error) => {
+                    let message = format!("{} can't be rolled\n\n{error}", action.name);
+                    self.popup.show(&message, false, (30, 20), |_, _| {});
+                    return;
+                }
+            };
+            let attack = Attack {
+                attacker: attacker_name,
+                bonus,
+                damage,
+                roll: None,
+            };
+            if let Err(error) = self.roll_attack(attacker, target, attack, uses) {
+                let message = format!("{} can't be rolled\n\n{error}", action.name);
+                self.popup.show(&message, false, (30, 20), |_, _| {});
+            }
+        } else if let Some(dc) = action.save_dc {
+            let mut targets = self.targets();
+            targets.retain(|target| *target != attacker);
+            if targets.is_empty() {
+                let message = format!("Mark the targets of {} first", action.name);
+                self.popup.show(&message, false, (30, 20), |_, _| {});
+                return;
+            }
+            let ability = action.save_ability.unwrap_or(Ability::Dex);
+            self.group_save(
+                targets,
+                Some(vec![ability.to_string(), dc.to_string(), damage]),
+                None,
+                uses,
+            );
+        } else {
+            let mut message = action.to_string();
+            if !action.description.is_empty() {
+                message = format!("{message}\n\n{}", action.description);
+            }
+            self.popup.show(&message, false, (40, 30), |_, _| {});
+        }
+    }
+
+    /// Rolls the attack and its damage on a hit and shows the result
+    fn roll_attack(
+        &mut self,
+        attacker: Uuid,
+        target: Uuid,
+        attack: Attack,
+        uses: Option<Command>,
+    ) -> Result<(), DiceError> {
+        let message = {
+            let mut tracker = self.tracker.blocking_lock();
+            let damage = attack
+                .damage
+                .as_ref()
+                .map(|(expr, damage_type)| (expr, *damage_type));
+            let Some(result) =
+                tracker.attack(attacker, target, attack.bonus, damage, attack.roll, uses)?
+            else {
+                return Ok(());
+            };
+            let (Some(attacker), Some(entity)) = (
+                tracker.get_entity_by_uuid(attacker),
+                tracker.get_entity_by_uuid(target),
+            ) else {
+                return Ok(());
+            };
+            let name = entity.display_name();
+            let outcome = match (result.critical, result.hit) {
+                (true, _) => "Critical hit!",
+                (false, true) => "Hit",
+                (false, false) => "Miss",
+            };
+            let mut message = format!(
+                "{} attacks {name}\n\nRolled {} {:+} = {} vs AC {}: {outcome}",
+                attacker.display_name(),
+                result.roll,
+                attack.bonus,
+                result.total,
+                result.ac
+            );
+            if let Some(damage) = &result.damage {
+                message += &format!("\n\nDamage: {damage}");
+            }
+            if let Some(taken) = &result.damage_taken {
+                message += &format!(
+                    "\n\n{name} takes {taken}\n\nHP: {}",
+                    entity.ref_array_string()[2]
+                );
+            }
+            message
+        };
+        self.popup.show(&message, false, (30, 30), |app, _| {
+            app.check_concentration()
+        });
+        Ok(())
+    }
+
+    /// Shows the form to add combatants, `values` keeps the input after an invalid submit
+    fn add_entities(&mut self, values: Option<Vec<String>>, error: Option<String>) {
+        let values = values.unwrap_or_else(|| {
+            ["", "Monster", "", "", "0", "1", ""]
+                .map(String::from)
+                .to_vec()
+        });
+        let fields: Vec<(&str, &str)> = NEW_ENTITY_FIELDS
+            .iter()
+            .zip(values.iter())
+            .map(|(label, value)| (*label, value.as_str()))
+            .collect();
+        self.form.show(
+            "Add Combatants",
+            &fields,
+            error,
+            |app, values| match parse_new_entities(&values) {
+                Ok(entities) => app.execute(Command::AddEntities(entities)),
+                Err(error) => app.add_entities(Some(values), Some(error)),
+            },
+        );
+    }
+
+    /// Asks after whom the selected delaying entity acts
+    fn resume_turn(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let tracker = self.tracker.blocking_lock();
+        let Some(entity) = tracker.get_entity_by_uuid(target).filter(|e| e.delaying) else {
+            return;
+        };
+        let order = tracker
+            .entities
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.uuid != target)
+            .map(|(i, e)| format!("{}: {}", i + 1, e.display_name()))
+            .join("\n");
+        let current = tracker.current_uuid();
+        let prompt = format!(
+            "After whom does {} act?\n\n{order}\n\nEnter the number or leave empty for the current combatant:",
+            entity.display_name()
+        );
+        drop(tracker);
+        self.popup.show(&prompt, true, (30, 50), move |app, input| {
+            let after = match input.trim() {
+                "" => current,
+                number => number.parse::<usize>().ok().and_then(|row| {
+                    app.tracker
+                        .blocking_lock()
+                        .entities
+                        .get(row.checked_sub(1)?)
+                        .map(|e| e.uuid)
+                }),
+            };
+            if let Some(after) = after {
+                app.execute(Command::ResumeTurn { target, after });
+                app.select_uuid(target);
+            }
+        });
+    }
+
+    /// Readies an action for the selected entity, or uses the one it readied
+    fn ready_action(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let Some(entity) = self
+            .tracker
+            .blocking_lock()
+            .get_entity_by_uuid(target)
+            .cloned()
+        else {
+            return;
+        };
+        let prompt = match &entity.readied {
+            Some(action) => format!(
+                "{} readied {action}.\n\nLeave empty if it was triggered or enter a new action and trigger:",
+                entity.display_name()
+            ),
+            None => format!(
+                "Enter the action {} readies and its trigger:",
+                entity.display_name()
+            ),
+        };
+        self.popup.show(&prompt, true, (30, 20), move |app, input| {
+            let action = input.trim();
+            if !action.is_empty() {
+                app.execute(Command::Ready {
+                    target,
+                    action: action.to_string(),
+                });
+            } else if entity.readied.is_some() {
+                app.execute(Command::UseReadied { target });
+            }
+        });
+    }
+
+    /// Asks before removing the selected entity, `k` keeps it in the combat at 0 HP instead
+    fn remove_entity(&mut self) {
+        let targets = self.targets();
+        let names = {
+            let tracker = self.tracker.blocking_lock();
+            targets
+                .iter()
+                .filter_map(|&target| tracker.get_entity_by_uuid(target))
+                .map(|e| e.display_name())
+                .join(", ")
+        };
+        if names.is_empty() {
+            return;
+        }
+        let prompt = format!(
+            "Remove {names} from the combat?\n\nEnter 'k' to keep them in the initiative at 0 HP instead"
+        );
+        self.popup.show(&prompt, true, (30, 20), move |app, input| {
+            let keep = input.trim().eq_ignore_ascii_case("k");
+            let current = app
+                .tracker
+                .blocking_lock()
+                .get_current_entity()
+                .map(|e| e.uuid);
+            let command = {
+                let tracker = app.tracker.blocking_lock();
+                Command::batch(
+                    targets
+                        .iter()
+                        .filter_map(|&target| tracker.get_entity_by_uuid(target))
+                        .map(|entity| {
+                            if keep {
+                                Command::Kill {
+                                    target: entity.uuid,
+                                }
+                            } else {
+                                Command::RemoveEntity {
+                                    target: entity.uuid,
+                                }
+                            }
+                        })
+                        .collect(),
+                )
+            };
+            if let Some(command) = command {
+                app.execute(command);
+            }
+            if !keep {
+                app.marked.retain(|uuid| !targets.contains(uuid));
+            }
+            let len = app.tracker.blocking_lock().entities.len();
+            if app.state.selected().is_some_and(|selected| selected >= len) {
+                app.state.select(len.checked_sub(1));
+            }
+            // removing the one whose turn it is starts the turn of the next one
+            if !keep && current.is_some_and(|uuid| targets.contains(&uuid)) {
+                app.start_turn();
+            }
+        });
+    }
+
+    /// Asks for the death save if the entity whose turn it is is dying.
+    /// `error` holds a rejected input and the reason, to show it again.
+    fn check_death_save(&mut self, error: Option<(String, String)>) {
+        let Some(entity) = self
+            .tracker
+            .blocking_lock()
+            .get_current_entity()
+            .filter(|e| e.is_dying())
+        else {
+            return;
+        };
+        let mut prompt = format!(
+            "{} is dying ({}).\n\nEnter the d20 roll, leave empty to auto-roll or enter 's' if stabilized:",
+            entity.display_name(),
+            entity.death_saves
+        );
+        if let Some((_, message)) = &error {
+            prompt = format!("{prompt}\n\nInvalid input: {message}");
+        }
+        let target = entity.uuid;
+        self.popup.show(&prompt, true, (30, 30), move |app, input| {
+            let trimmed = input.trim();
+            let (command, roll) = if trimmed.eq_ignore_ascii_case("s") {
+                (Command::Stabilize { target }, None)
+            } else if trimmed.is_empty() {
+                let d20 = "1d20".parse::<DiceExpr>().expect("valid dice expression");
+                let roll = app
+                    .tracker
+                    .blocking_lock()
+                    .roll(&d20)
+                    .expect("a d20 can't overflow")
+                    .total;
+                (Command::DeathSave { target, roll }, Some(roll))
+            } else {
+                match trimmed.parse::<i32>() {
+                    Ok(roll) if (1..=20).contains(&roll) => {
+                        (Command::DeathSave { target, roll }, Some(roll))
+                    }
+                    _ => {
+                        let reason = "the roll must be a number from 1 to 20 or 's'".to_string();
+                        return app.check_death_save(Some((input, reason)));
+                    }
+                }
+            };
+            let mut tracker = app.tracker.blocking_lock();
+            let before = entity.death_saves.clone();
+            if !tracker.execute(command) {
+                return;
+            }
+            let Some(entity) = tracker.get_entity_by_uuid(target) else {
+                return;
+            };
+            let state = if entity.is_dead() {
+                "dies".to_string()
+            } else if entity.current_hp > 0 {
+                "regains 1 HP".to_string()
+            } else if entity.death_saves.stable {
+                "is stable".to_string()
+            } else if entity.death_saves.successes > before.successes {
+                format!("success ({})", entity.death_saves)
+            } else {
+                format!("failure ({})", entity.death_saves)
+            };
+            let message = match roll {
+                Some(roll) => format!("{}: death save {roll}, {state}", entity.display_name()),
+                None => format!("{} {state}", entity.display_name()),
+            };
+            drop(tracker);
+            app.popup.show(&message, false, (30, 20), |_, _| {});
+        });
+        if let Some((input, _)) = error {
+            self.popup.input.insert_str(input);
+        }
+    }
+
+    /// Starts concentrating on a spell, an empty input ends the concentration
+    fn change_concentration(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let current = self
+            .tracker
+            .blocking_lock()
+            .get_entity_by_uuid(target)
+            .and_then(|e| e.concentration.clone());
+        let prompt = match current {
+            Some(spell) => format!(
+                "Concentrating on {spell}.\n\nEnter a new spell or leave empty to end concentration:"
+            ),
+            None => "Enter the spell to concentrate on:".to_string(),
+        };
+        self.popup.show(&prompt, true, (30, 20), move |app, input| {
+            let spell = input.trim();
+            if spell.is_empty() {
+                app.execute(Command::EndConcentration { target });
+            } else {
+                app.execute(Command::StartConcentration {
+                    target,
+                    spell: spell.to_string(),
+                });
+            }
+        });
+    }
+
+    fn undo(&mut self) {
+        if self.tracker.blocking_lock().undo().is_none() {
+            debug!("Nothing to undo");
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.tracker.blocking_lock().redo().is_none() {
+            debug!("Nothing to redo");
+        }
+    }
+
+    fn change_hp(&mut self, change: HpChange) {
+        let targets = self.targets();
+        if !targets.is_empty() {
+            self.change_hp_popup(targets, change, None);
+        }
+    }
+
+    /// Asks for a damage, heal or temporary HP amount, which can be a dice expression like `2d6+3`.
+    /// Damage can be followed by a damage type, e.g. `14 fire`.
+    /// The amount is rolled once and applied to all `targets` as one command.
+    /// `error` holds the previous input and the reason it was rejected.
+    fn change_hp_popup(
+        &mut self,
+        targets: Vec<Uuid>,
+        change: HpChange,
+        error: Option<(String, String)>,
+    ) {
+        let mut prompt = match change {
+            HpChange::Damage => "Enter damage amount:",
+            HpChange::Heal => "Enter heal amount:",
+            HpChange::TempHp => "Enter temporary HP:",
+        }
+        .to_string();
+        if targets.len() > 1 {
+            prompt = format!("{prompt}\n\nApplies to {} marked combatants", targets.len());
+        }
+        if let Some((_, message)) = &error {
+            prompt = format!("{prompt}\n\nInvalid input: {message}");
+        }
+        self.popup
+            .show(&prompt, true, (30, 20), move |app, input_amount| {
+                let (amount_input, damage_type) = match change {
+                    HpChange::Damage => split_damage_type(&input_amount),
+                    _ => (input_amount.as_str(), None),
+                };
+                let rolled = amount_input.parse::<DiceExpr>().and_then(|expr| {
+                    let result = app.tracker.blocking_lock().roll(&expr)?;
+                    Ok((expr, result))
+                });
+                let (expr, result) = match rolled {
+                    Ok(rolled) => rolled,
+                    Err(e) => {
+                        app.change_hp_popup(
+                            targets.clone(),
+                            change,
+                            Some((input_amount, e.to_string())),
+                        );
+                        return;
+                    }
+                };
+                let (message, adjusted) = {
+                    let mut tracker = app.tracker.blocking_lock();
+                    let amount = result.total.max(0);
+                    // resistances etc. depend on the target, look them up before applying
+                    let outcomes: Vec<_> = targets
+                        .iter()
+                        .map(|&target| {
+                            (change == HpChange::Damage)
+                                .then(|| tracker.damage_outcome(target, amount, damage_type))
+                                .flatten()
+                        })
+                        .collect();
+                    let commands = targets
+                        .iter()
+                        .map(|&target| match change {
+                            HpChange::Damage => Command::Damage {
+                                target,
+                                amount,
+                                damage_type,
+                                critical: false,
+                            },
+                            HpChange::Heal => Command::Heal { target, amount },
+                            HpChange::TempHp => Command::SetTempHp { target, amount },
+                        })
+                        .collect();
+                    if let Some(command) = Command::batch(commands) {
+                        tracker.execute(command);
+                    }
+                    let mut adjusted = false;
+                    let mut lines = Vec::new();
+                    for (&target, outcome) in targets.iter().zip(outcomes) {
+                        let Some(entity) = tracker.get_entity_by_uuid(target) else {
+                            continue;
+                        };
+                        let name = entity.display_name();
+                        adjusted |= outcome
+                            .as_ref()
+                            .is_some_and(|outcome| !outcome.modifiers.is_empty());
+                        // temporary hit points don't stack, a higher value is kept
+                        let kept_temp_hp = change == HpChange::TempHp && entity.temp_hp > amount;
+                        adjusted |= kept_temp_hp;
+                        let summary = match (change, &outcome) {
+                            (HpChange::Damage, Some(outcome)) => format!("takes {outcome}"),
+                            (HpChange::Damage, None) => format!("takes {amount} damage"),
+                            (HpChange::Heal, _) => format!("heals {amount} HP"),
+                            (HpChange::TempHp, _) if kept_temp_hp => {
+                                format!("keeps its higher {} temporary HP", entity.temp_hp)
+                            }
+                            (HpChange::TempHp, _) => format!("gains {amount} temporary HP"),
+                        };
+                        lines.push((name, summary, entity.ref_array_string()[2].clone()));
+                    }
+                    let message = match lines.as_slice() {
+                        [(name, summary, hp)] => {
+                            format!("{name} {summary}\n\n{result}\n\nHP: {hp}")
+                        }
+                        lines => format!(
+                            "{result}\n\n{}",
+                            lines
+                                .iter()
+                                .map(|(name, summary, hp)| format!("{name} {summary}, HP: {hp}"))
+                                .join("\n")
+                        ),
+                    };
+                    (message, adjusted)
+                };
+                // a plain number doesn't need a breakdown unless resistances changed it
+                // or it was applied to several combatants
+                if adjusted || targets.len() > 1 || !(expr.is_constant() && expr.terms.len() == 1) {
+                    app.popup.show(&message, false, (30, 20), |app, _| {
+                        app.check_concentration()
+                    });
+                } else {
+                    app.check_concentration();
+                }
+            });
+        if let Some((input, _)) = error {
+            self.popup.input.insert_str(input);
+        }
+    }
+
+    fn set_initiative(&mut self) {
+        let prompt = "Enter Initiative:";
+        if let Some(target) = self.selected_uuid() {
+            self.popup
+                .show(prompt, true, (30, 20), move |app, input_amount| {
+                    if let Ok(initiative) = input_amount.parse::<i32>() {
+                        app.execute(Command::SetInitiative { target, initiative });
+                        // the entity moved in the table, keep it selected
+                        app.select_uuid(target);
+                    }
+                });
+        };
+    }
+
+    /// Adds a custom effect by name, or removes it if the entity already has it
+    fn change_effects(&mut self) {
+        let Some(target) = self.selected_uuid() else {
+            return;
+        };
+        let tracker = self.tracker.blocking_lock();
+        let current_uuid = tracker.current_uuid();
+        let Some(entity) = tracker.get_entity_by_uuid(target) else {
+            return;
+        };
+        let active = if entity.effects.is_empty() {
+            "none".to_string()
+        } else {
+            entity.effects.iter().join(", ")
+        };
+        drop(tracker);
+
+        self.popup.show(
+            &format!(
+                "Active effects: {active}\n\nEnter an effect to add or an active one to remove:\nname [value] [duration]\nduration: 3r = rounds, eot = until end of the active creature's next turn, save = until save\ne.g. Exhaustion 3, Bless 10r"
+            ),
+            true,
+            (30, 40),
+            move |app, input| {
+                let Some(effect) = parse_effect(&input, current_uuid) else {
+                    return;
+                };
+                let remove = effect.value.is_none()
+                    && effect.duration.is_none()
+                    && app
+                        .tracker
+                        .blocking_lock()
+                        .get_entity_by_uuid(target)
+                        .is_some_and(|e| e.get_effect(&effect.name).is_some());
+                if remove {
+                    app.execute(Command::RemoveEffect {
+                        target,
+                        name: effect.name,
+                    });
+                } else {
+                    app.execute(Command::AddEffect { target, effect });
+                }
+            },
+        );
+    }
+
+    /// Toggles a condition of the marked entities, or the selected one if none are marked.
+    /// A condition only some of them have is added to the others.
+    fn change_conditions(&mut self) {
+        let targets = self.targets();
+        let tracker = self.tracker.blocking_lock();
+        let current_uuid = tracker.get_current_entity().map(|e| e.uuid);
+        let entities: Vec<&Entity> = targets
+            .iter()
+            .filter_map(|&target| tracker.get_entity_by_uuid(target))
+            .collect();
+        if entities.is_empty() {
+            return;
+        }
+
+        let all_conditions: Vec<Condition> = vec![
+            Condition::Blinded,
+            Condition::Charmed,
+            Condition::Deafened,
+            Condition::Frightened,
+            Condition::Grappled,
+            Condition::Incapacitated,
+            Condition::Invisible,
+            Condition::Paralyzed,
+            Condition::Petrified,
+            Condition::Poisoned,
+            Condition::Prone,
+            Condition::Restrained,
+            Condition::Stunned,
+            Condition::Unconscious,
+        ];
+
+        let prompt_text = all_conditions
+            .iter()
+            .enumerate()
+            .map(|(i, condition)| {
+                let count = entities
+                    .iter()
+                    .filter(|e| e.conditions.contains(condition))
+                    .count();
+                let marker = match count {
+                    0 => "[ ]",
+                    count if count == entities.len() => "[X]",
+                    _ => "[~]",
+                };
+                let duration = match entities.as_slice() {
+                    [entity] => entity.condition_durations.get(condition),
+                    _ => None,
+                };
+                match duration {
+                    Some(duration) => {
+                        format!("{} {} - {} ({})", marker, i + 1, condition, duration)
+                    }
+                    None => format!("{} {} - {}", marker, i + 1, condition),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        drop(tracker);
+
+        self.popup.show(
+            &format!(
+                "Select condition to toggle:\n\n{}\n\nOptional duration after the number:\nrounds, e = until end of the active creature's next turn, s = until save",
+                prompt_text
+            ),
+            true,
+            (30, 50),
+            move |app, input| {
+                let mut parts = input.split_whitespace();
+                let Some(Ok(index)) = parts.next().map(|index| index.parse::<usize>()) else {
+                    return;
+                };
+                let duration = match parts.next() {
+                    None => None,
+                    Some("s") => Some(ConditionDuration::UntilSave),
+                    Some("e") => current_uuid.map(|source| ConditionDuration::EndOfSourceTurn {
+                        source,
+                        skip_current: false,
+                    }),
+                    Some(rounds) => match rounds.parse::<u32>() {
+                        Ok(rounds) if rounds > 0 => Some(ConditionDuration::Rounds(rounds)),
+                        _ => return,
+                    },
+                };
+                if index == 0 || index > all_conditions.len() {
+                    return;
+                }
+                let condition = &all_conditions[index - 1];
+                let command = {
+                    let tracker = app.tracker.blocking_lock();
+                    let has_condition = |target: &Uuid| {
+                        tracker
+                            .get_entity_by_uuid(*target)
+                            .is_some_and(|e| e.conditions.contains(condition))
+                    };
+                    // remove it only if all of them have it
+                    let remove = targets.iter().all(has_condition);
+                    Command::batch(
+                        targets
+                            .iter()
+                            .filter(|target| remove || !has_condition(target))
+                            .map(|&target| Command::ToggleCondition {
+                                target,
+                                condition: condition.clone(),
+                                duration: duration.clone(),
+                            })
+                            .collect(),
+                    )
+                };
+                if let Some(command) = command {
+                    app.execute(command);
+                }
+            },
+        );
+    }
+
+    fn exit(&mut self) {
+        info!("Application stopped.");
+        self.exit = true;
+    }
+
+    fn confirm_close(&mut self) {
+        self.popup.show(
+            "Do you want to close the application?",
+            false,
+            (30, 20),
+            |app, _| app.exit(),
+        );
+    }
+
+    fn backup(&mut self) -> Result<()> {
+        debug!("Writing state to file {}", &self.output_file.display());
+        fs::write(&self.output_file, self.tracker.blocking_lock().to_yaml())?;
+        Ok(())
+    }
+}
+
+const NEW_ENTITY_FIELDS: [&str; 7] = [
+    "Name",
+    "Type",
+    "AC",
+    "HP",
+    "Initiative modifier",
+    "Count",
+    "Initiative (empty to roll)",
+];
+
+/// Creates the entities from the values of the add combatants form, see [`NEW_ENTITY_FIELDS`]
+fn parse_new_entities(values: &[String]) -> std::result::Result<Vec<Entity>, String> {
+    let [name, entity_type, ac, hp, initiative_modifier, count, initiative] = values else {
+        return Err("missing fields".to_string());
+    };
+    let number = |value: &str, field: &str| {
+        value
+            .trim()
+            .parse::<i32>()
+            .map_err(|_| format!("{field} needs to be a number"))
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Name can't be empty".to_string());
+    }
+    let entity_type: EntityType = entity_type.parse()?;
+    let ac = number(ac, "AC")?;
+    let hp = number(hp, "HP")?;
+    if hp <= 0 {
+        return Err("HP needs to be positive".to_string());
+    }
+    let initiative_modifier = number(initiative_modifier, "Initiative modifier")?;
+    let count = number(count, "Count")?;
+    if !(1..=50).contains(&count) {
+        return Err("Count needs to be between 1 and 50".to_string());
+    }
+    let initiative = match initiative.trim() {
+        "" => None,
+        initiative => Some(number(initiative, "Initiative")?),
+    };
+    Ok((0..count)
+        .map(|_| {
+            let mut entity = Entity::new(name, entity_type.clone(), initiative_modifier, ac, hp);
+            entity.initiative = initiative;
+            entity
+        })
+        .collect())
+}
+
+const GROUP_SAVE_FIELDS: [&str; 3] = ["Ability", "DC", "Damage on a failed save (e.g. 8d6 fire)"];
+
+/// A saving throw of several entities, half of the damage is taken on a success
+struct GroupSave {
+    ability: Ability,
+    dc: i32,
+    damage: Option<(DiceExpr, Option<DamageType>)>,
+}
+
+/// Parses the values of the saving throw form, see [`GROUP_SAVE_FIELDS`]
+fn parse_group_save(values: &[String]) -> std::result::Result<GroupSave, String> {
+    let [ability, dc, damage] = values else {
+        return Err("missing fields".to_string());
+    };
+    let ability: Ability = ability.parse()?;
+    let dc = dc
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| "DC needs to be a number".to_string())?;
+    Ok(GroupSave {
+        ability,
+        dc,
+        damage: parse_damage(damage)?,
+    })
+}
+
+const ATTACK_FIELDS: [&str; 4] = [
+    "Attacker",
+    "Attack bonus",
+    "Damage (e.g. 1d6+2 slashing)",
+    "d20 roll (empty to roll)",
+];
+
+struct Attack {
+    attacker: String,
+    bonus: i32,
+    damage: Option<(DiceExpr, Option<DamageType>)>,
+    /// The natural d20 roll if it was rolled at the table
+    roll: Option<i32>,
+}
+
+/// Parses the values of the attack form, see [`ATTACK_FIELDS`]
+fn parse_attack(values: &[String]) -> std::result::Result<Attack, String> {
+    let [attacker, bonus, damage, roll] = values else {
+        return Err("missing fields".to_string());
+    };
+    let bonus = bonus
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| "Attack bonus needs to be a number".to_string())?;
+    let roll = match roll.trim() {
+        "" => None,
+        roll => match roll.parse::<i32>() {
+            Ok(roll) if (1..=20).contains(&roll) => Some(roll),
+            _ => return Err("d20 roll needs to be between 1 and 20".to_string()),
+        },
+    };
+    Ok(Attack {
+        attacker: attacker.trim().to_string(),
+        bonus,
+        damage: parse_damage(damage)?,
+        roll,
+    })
+}
+
+/// Parses damage like `8d6 fire`, `None` if it's empty
+fn parse_damage(
+    input: &str,
+) -> std::result::Result<Option<(DiceExpr, Option<DamageType>)>, String> {
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    let (dice, damage_type) = split_damage_type(input);
+    let expr = dice
+        .parse::<DiceExpr>()
+        .map_err(|e| format!("Damage: {e}"))?;
+    Ok(Some((expr, damage_type)))
+}
+
+/// Parses an effect like `Exhaustion 3`, `Bless 10r` or `Hex eot`.
+/// `source` is the entity whose turn it is, it applied the effect.
+fn parse_effect(input: &str, source: Option<Uuid>) -> Option<Effect> {
+    let mut words: Vec<&str> = input.split_whitespace().collect();
+    let duration = match words.last().map(|w| w.to_ascii_lowercase()) {
+        Some(w) if w == "save" => Some(ConditionDuration::UntilSave),
+        Some(w) if w == "eot" => source.map(|source| ConditionDuration::EndOfSourceTurn {
+            source,
+            skip_current: false,
+        }),
+        Some(w) => w
+            .strip_suffix('r')
+            .and_then(|rounds| rounds.parse::<u32>().ok())
+            .filter(|rounds| *rounds > 0)
+            .map(ConditionDuration::Rounds),
+        None => None,
+    };
+    if duration.is_some() {
+        words.pop();
+    }
+    let value = words.last().and_then(|w| w.parse::<i32>().ok());
+    if value.is_some() {
+        words.pop();
+    }
+    if words.is_empty() {
+        return None;
+    }
+    Some(Effect {
+        name: words.join(" "),
+        value,
+        duration,
+        source,
+        triggers: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tui::utils::load_combat_yaml;
+
+    use super::*;
+    use core::combat::entity::{Action, LimitedAbility};
+
+    use std::env::temp_dir;
+    use std::fs::File;
+    use std::io::Write;
 
     #[test]
     fn test_player_character_yaml() {
@@ -412,6 +2014,8 @@ players:
             player_characters: Some(players_file_path),
             output: None,
             stdout: false,
+            skip: None,
+            bestiary: None,
         };
 
         let tracker = Arc::new(Mutex::new(CombatTracker::from_yaml(
@@ -471,6 +2075,7 @@ monsters:
             r#"
 players:
   - name: Samwise
+    initiative: 8
     initiative_modifier: 2
     ac: 16
     max_hp: 63
@@ -480,6 +2085,7 @@ monsters:
     stats:
         name: Goblin
         entity_type: Monster
+        initiative: 15
         initiative_modifier: 2
         ac: 12
         max_hp: 10
@@ -493,6 +2099,8 @@ monsters:
             player_characters: None,
             output: None,
             stdout: false,
+            skip: None,
+            bestiary: None,
         };
 
         let tracker = Arc::new(Mutex::new(CombatTracker::from_yaml(
@@ -508,4 +2116,803 @@ monsters:
             "Goblin".to_string()
         );
     }
+
+    #[test]
+    fn test_parse_effect() {
+        let source = Uuid::new_v4();
+        let effect = parse_effect("Exhaustion 3", None).unwrap();
+        assert_eq!(effect.name, "Exhaustion");
+        assert_eq!(effect.value, Some(3));
+        assert_eq!(effect.duration, None);
+
+        let effect = parse_effect(" Hunter's Mark 10r ", Some(source)).unwrap();
+        assert_eq!(effect.name, "Hunter's Mark");
+        assert_eq!(effect.value, None);
+        assert_eq!(effect.duration, Some(ConditionDuration::Rounds(10)));
+        assert_eq!(effect.source, Some(source));
+
+        let effect = parse_effect("Hex eot", Some(source)).unwrap();
+        assert!(matches!(
+            effect.duration,
+            Some(ConditionDuration::EndOfSourceTurn { .. })
+        ));
+
+        assert!(parse_effect("3 save", None).is_none());
+        assert!(parse_effect("", None).is_none());
+    }
+
+    fn enter() -> KeyEvent {
+        KeyEvent::new(event::KeyCode::Enter, event::KeyModifiers::NONE)
+    }
+
+    /// An app tracking `entities`, the combat file is only used to name the backup
+    fn test_app(entities: Vec<Entity>) -> App<'static> {
+        let args = Args {
+            combat_file: temp_dir().join("combat.yaml"),
+            player_characters: None,
+            output: None,
+            stdout: false,
+            skip: None,
+            bestiary: None,
+        };
+        let mut tracker = CombatTracker::new();
+        tracker.add_entities(entities);
+        App::new_with_tracker(&args, Arc::new(Mutex::new(tracker))).unwrap()
+    }
+
+    #[test]
+    fn test_help() {
+        let mut app = test_app(vec![Entity::new("Orc", EntityType::Monster, 1, 13, 15)]);
+        let key = |c: char| KeyEvent::new(event::KeyCode::Char(c), event::KeyModifiers::NONE);
+        app.handle_key_event(key('?')).unwrap();
+        assert!(app.show_help);
+        // the key that closes the help doesn't do anything else
+        app.handle_key_event(key('l')).unwrap();
+        assert!(!app.show_help);
+        assert!(app.show_log);
+    }
+
+    #[test]
+    fn test_damage_popup_accepts_dice_expressions() {
+        let mut orc = Entity::new("Orc", EntityType::Monster, 1, 13, 15);
+        orc.initiative = Some(10);
+        let mut app = test_app(vec![orc]);
+
+        // invalid input keeps the popup open and reports the error
+        app.change_hp(HpChange::Damage);
+        app.popup.input.insert_str("2d6+x");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.active);
+        assert!(app.popup.prompt.contains("Invalid input"));
+        assert_eq!(app.popup.input.lines().join(""), "2d6+x");
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 15);
+
+        // a dice expression is rolled and its breakdown shown
+        app.popup.input.select_all();
+        app.popup.input.cut();
+        app.popup.input.insert_str("1d4+2");
+        app.handle_popup_key_event(enter()).unwrap();
+        let hp = app.tracker.blocking_lock().entities[0].current_hp;
+        assert!((9..=12).contains(&hp));
+        assert!(app.popup.active);
+        assert!(app.popup.prompt.contains("1d4["));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+
+        // plain numbers still work and close the popup right away
+        app.change_hp(HpChange::Heal);
+        app.popup.input.insert_str("20");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 15);
+
+        // both changes can be undone and redone
+        app.undo();
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, hp);
+        app.undo();
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 15);
+        app.redo();
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, hp);
+
+        // any number the parser accepts can be healed without overflowing
+        app.change_hp(HpChange::Heal);
+        app.popup.input.insert_str("2147483647");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 15);
+    }
+
+    #[test]
+    fn test_damage_popup_shows_resistances() {
+        let mut golem = Entity::new("Golem", EntityType::Monster, 1, 13, 30);
+        golem.initiative = Some(10);
+        golem.resistances.insert(DamageType::Fire);
+        let mut app = test_app(vec![golem]);
+
+        app.change_hp(HpChange::Damage);
+        app.popup.input.insert_str("10 fire");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.active);
+        assert!(app
+            .popup
+            .prompt
+            .contains("Golem takes 5 fire damage [resistant, halved from 10]"));
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 25);
+    }
+
+    #[test]
+    fn test_temp_hp_popup() {
+        let mut orc = Entity::new("Orc", EntityType::Monster, 1, 13, 15);
+        orc.initiative = Some(10);
+        orc.temp_hp = 8;
+        let mut app = test_app(vec![orc]);
+
+        // a lower value is ignored and the popup says so
+        app.change_hp(HpChange::TempHp);
+        app.popup.input.insert_str("5");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.active);
+        assert!(app
+            .popup
+            .prompt
+            .contains("Orc keeps its higher 8 temporary HP"));
+        app.handle_popup_key_event(enter()).unwrap();
+
+        app.change_hp(HpChange::TempHp);
+        app.popup.input.insert_str("10");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(app.tracker.blocking_lock().entities[0].temp_hp, 10);
+    }
+
+    #[test]
+    fn test_concentration_prompt() {
+        let mut wizard = Entity::new("Wizard", EntityType::Player, 2, 12, 30);
+        wizard.initiative = Some(10);
+        wizard.concentration = Some("Bless".to_string());
+        let mut app = test_app(vec![wizard]);
+
+        app.change_hp(HpChange::Damage);
+        app.popup.input.insert_str("8");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Wizard is concentrating on Bless"));
+        let log_len = app.tracker.blocking_lock().log.len();
+
+        // a typo isn't rolled as an empty input would be
+        app.popup.input.insert_str("1O");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Wizard is concentrating on Bless"));
+        assert!(app.popup.prompt.contains("Invalid input"));
+        assert_eq!(app.popup.input.lines().join(""), "1O");
+        assert_eq!(app.tracker.blocking_lock().log.len(), log_len);
+
+        app.popup.input.select_all();
+        app.popup.input.cut();
+        app.popup.input.insert_str("15");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.contains("concentration on Bless holds"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(
+            app.tracker.blocking_lock().entities[0]
+                .concentration
+                .as_deref(),
+            Some("Bless")
+        );
+    }
+
+    #[test]
+    fn test_death_save_prompt_on_turn() {
+        let mut merry = Entity::new("Merry", EntityType::Player, 2, 16, 20);
+        merry.initiative = Some(15);
+        merry.current_hp = 0;
+        let mut orc = Entity::new("Orc", EntityType::Monster, 1, 13, 15);
+        orc.initiative = Some(10);
+        let mut app = test_app(vec![merry, orc]);
+
+        // the orc's turn doesn't ask for anything
+        app.next_turn();
+        assert!(!app.popup.active);
+
+        app.next_turn();
+        assert!(app.popup.active);
+        assert!(app.popup.prompt.contains("Merry is dying"));
+        app.popup.input.insert_str("12");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Merry: death save 12, success (S ●○○ F ○○○)"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(
+            app.tracker.blocking_lock().entities[0]
+                .death_saves
+                .successes,
+            1
+        );
+
+        // rolls outside of 1-20 ask again and say why
+        app.next_turn();
+        app.next_turn();
+        app.popup.input.insert_str("25");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.contains("Merry is dying"));
+        assert!(app.popup.prompt.contains("Invalid input"));
+        assert_eq!(app.popup.input.lines().join(""), "25");
+        app.popup.input.select_all();
+        app.popup.input.cut();
+        app.popup.input.insert_str("s");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.contains("Merry is stable"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.tracker.blocking_lock().entities[0].death_saves.stable);
+
+        // stable characters aren't asked again
+        app.next_turn();
+        app.next_turn();
+        assert!(!app.popup.active);
+    }
+
+    #[test]
+    fn test_add_entities_form() {
+        let mut orc = Entity::new("Orc", EntityType::Monster, 1, 13, 15);
+        orc.initiative = Some(10);
+        let mut app = test_app(vec![orc]);
+        let tab = KeyEvent::new(event::KeyCode::Tab, event::KeyModifiers::NONE);
+        // the combat is underway, so the Orc keeps its turn
+        app.next_turn();
+
+        // a missing name is rejected and the form is shown again
+        app.add_entities(None, None);
+        app.handle_form_key_event(tab).unwrap();
+        app.handle_form_key_event(tab).unwrap();
+        app.form.inputs[app.form.focus].insert_str("13");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(app.form.active);
+        assert_eq!(app.form.error.as_deref(), Some("Name can't be empty"));
+        assert_eq!(app.form.values()[2], "13");
+
+        app.form.inputs[0].insert_str("Wolf");
+        app.form.inputs[3].insert_str("11");
+        app.form.inputs[5].delete_line_by_head();
+        app.form.inputs[5].insert_str("2");
+        app.form.inputs[6].insert_str("14");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(!app.form.active);
+
+        let tracker = app.tracker.blocking_lock();
+        assert_eq!(tracker.entities.len(), 3);
+        assert_eq!(tracker.entities[0].display_name(), "Wolf (1)");
+        assert_eq!(tracker.entities[1].display_name(), "Wolf (2)");
+        assert_eq!(tracker.entities[1].initiative, Some(14));
+        assert_eq!(tracker.entities[1].max_hp, 11);
+        assert_eq!(tracker.entities[tracker.current_turn].name, "Orc");
+    }
+
+    #[test]
+    fn test_parse_new_entities() {
+        let values = |values: [&str; 7]| values.map(String::from).to_vec();
+        let entities =
+            parse_new_entities(&values(["Guard", "npc", "16", "11", "1", "1", ""])).unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].entity_type, EntityType::Npc);
+        assert_eq!(entities[0].initiative, None);
+        assert_eq!(
+            parse_new_entities(&values(["Guard", "npc", "16", "lots", "1", "1", ""])),
+            Err("HP needs to be a number".to_string())
+        );
+        assert!(
+            parse_new_entities(&values(["Guard", "dragon", "16", "11", "1", "1", ""])).is_err()
+        );
+        assert!(parse_new_entities(&values(["Guard", "npc", "16", "11", "1", "0", ""])).is_err());
+    }
+
+    #[test]
+    fn test_group_save() {
+        let mut rogue = Entity::new("Rogue", EntityType::Player, 4, 15, 30);
+        rogue.initiative = Some(20);
+        rogue.saving_throws.insert(Ability::Dex, 30);
+        let goblin = || {
+            let mut goblin = Entity::new("Goblin", EntityType::Monster, 2, 15, 40);
+            goblin.initiative = Some(10);
+            goblin.saving_throws.insert(Ability::Dex, -30);
+            goblin
+        };
+        let mut app = test_app(vec![rogue, goblin(), goblin()]);
+
+        // marking moves on to the next row
+        app.toggle_mark();
+        app.toggle_mark();
+        assert_eq!(app.state.selected(), Some(2));
+        app.toggle_mark();
+        app.state.select(Some(1));
+        app.toggle_mark();
+        assert_eq!(app.marked.len(), 2);
+
+        let targets = app.targets();
+        app.group_save(targets, None, None, None);
+        assert_eq!(app.form.title, "Saving Throw of 2 Combatants");
+        app.form.inputs[1].insert_str("15");
+        app.form.inputs[2].insert_str("2d6+x");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(app.form.active);
+        assert!(app.form.error.as_deref().unwrap().starts_with("Damage:"));
+
+        app.form.inputs[2].delete_line_by_head();
+        app.form.inputs[2].insert_str("20 fire");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(!app.form.active);
+        assert!(app.popup.active);
+        assert!(app.popup.prompt.contains("DEX save DC 15"));
+        assert!(app.popup.prompt.contains("Rogue: "));
+        assert!(app.popup.prompt.contains("succeeds, takes 10, HP 20/30"));
+        assert!(app.popup.prompt.contains("fails, takes 20, HP 20/40"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+        let hp: Vec<i32> = app
+            .tracker
+            .blocking_lock()
+            .entities
+            .iter()
+            .map(|e| e.current_hp)
+            .collect();
+        assert_eq!(hp, vec![20, 40, 20]);
+
+        // one undo reverts the damage of all targets
+        app.undo();
+        let hp: Vec<i32> = app
+            .tracker
+            .blocking_lock()
+            .entities
+            .iter()
+            .map(|e| e.current_hp)
+            .collect();
+        assert_eq!(hp, vec![30, 40, 40]);
+    }
+
+    #[test]
+    fn test_batch_actions() {
+        let mut fighter = Entity::new("Fighter", EntityType::Player, 1, 18, 40);
+        fighter.initiative = Some(20);
+        let monster = |name: &str, initiative: i32| {
+            let mut monster = Entity::new(name, EntityType::Monster, 2, 15, 20);
+            monster.initiative = Some(initiative);
+            monster
+        };
+        let mut app = test_app(vec![
+            fighter,
+            monster("Goblin", 12),
+            monster("Goblin", 12),
+            monster("Wolf", 8),
+        ]);
+        let hp = |app: &App| {
+            app.tracker
+                .blocking_lock()
+                .entities
+                .iter()
+                .map(|e| e.current_hp)
+                .collect::<Vec<_>>()
+        };
+
+        let ctrl_a = KeyEvent::new(event::KeyCode::Char('a'), event::KeyModifiers::CONTROL);
+        app.handle_key_event(ctrl_a).unwrap();
+        assert_eq!(app.marked.len(), 3);
+        app.handle_key_event(ctrl_a).unwrap();
+        assert!(app.marked.is_empty());
+
+        // the goblins are marked as a group
+        app.state.select(Some(2));
+        app.toggle_mark_group();
+        assert_eq!(app.targets().len(), 2);
+
+        app.change_hp(HpChange::Damage);
+        assert!(app.popup.prompt.contains("Applies to 2 marked combatants"));
+        app.popup.input.insert_str("7");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Goblin (1) takes 7 damage, HP: 13/20"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(hp(&app), vec![40, 13, 13, 20]);
+        app.undo();
+        assert_eq!(hp(&app), vec![40, 20, 20, 20]);
+
+        // a condition only one of them has is added to the other
+        app.execute(Command::ToggleCondition {
+            target: app.targets()[0],
+            condition: Condition::Prone,
+            duration: None,
+        });
+        app.change_conditions();
+        assert!(app.popup.prompt.contains("[~] 11 - Prone"));
+        app.popup.input.insert_str("11");
+        app.handle_popup_key_event(enter()).unwrap();
+        let prone = |app: &App| {
+            app.tracker
+                .blocking_lock()
+                .entities
+                .iter()
+                .filter(|e| e.conditions.contains(&Condition::Prone))
+                .count()
+        };
+        assert_eq!(prone(&app), 2);
+        app.change_conditions();
+        assert!(app.popup.prompt.contains("[X] 11 - Prone"));
+        app.popup.input.insert_str("11");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(prone(&app), 0);
+        app.undo();
+        assert_eq!(prone(&app), 2);
+
+        app.remove_entity();
+        assert!(app.popup.prompt.contains("Remove Goblin (1), Goblin (2)"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities.len(), 2);
+        assert!(app.marked.is_empty());
+        app.undo();
+        assert_eq!(app.tracker.blocking_lock().entities.len(), 4);
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut goblin = Entity::new("Goblin", EntityType::Monster, 2, 15, 7);
+        goblin.initiative = Some(15);
+        let mut fighter = Entity::new("Fighter", EntityType::Player, 1, 18, 40);
+        fighter.initiative = Some(10);
+        let mut app = test_app(vec![goblin, fighter]);
+        app.state.select(Some(1));
+
+        // the attacker defaults to the entity whose turn it is
+        app.attack(None, None);
+        assert_eq!(app.form.title, "Attack Fighter");
+        assert_eq!(app.form.values()[0], "Goblin");
+        app.form.inputs[0].delete_line_by_head();
+        app.form.inputs[0].insert_str("Bugbear");
+        app.form.inputs[1].insert_str("+4");
+        app.form.inputs[2].insert_str("1d6+2 slashing");
+        app.form.inputs[3].insert_str("20");
+        app.handle_form_key_event(enter()).unwrap();
+        assert_eq!(
+            app.form.error.as_deref(),
+            Some("Unknown attacker 'Bugbear'")
+        );
+
+        app.form.inputs[0].delete_line_by_head();
+        app.form.inputs[0].insert_str("goblin");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(!app.form.active);
+        assert!(app
+            .popup
+            .prompt
+            .contains("Rolled 20 +4 = 24 vs AC 18: Critical hit!"));
+        assert!(app.popup.prompt.contains("Damage: 2d6["));
+        assert!(app.popup.prompt.contains("Fighter takes"));
+        app.handle_popup_key_event(enter()).unwrap();
+        let hp = app.tracker.blocking_lock().entities[1].current_hp;
+        assert!((26..=36).contains(&hp));
+
+        // a miss doesn't roll damage
+        app.attack(None, None);
+        app.form.inputs[1].insert_str("4");
+        app.form.inputs[2].insert_str("1d6+2");
+        app.form.inputs[3].insert_str("13");
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.ends_with("17 vs AC 18: Miss"));
+        assert_eq!(app.tracker.blocking_lock().entities[1].current_hp, hp);
+    }
+
+    #[test]
+    fn test_use_action() {
+        let mut dragon = Entity::new("Dragon", EntityType::Monster, 0, 18, 100);
+        dragon.initiative = Some(15);
+        dragon.actions = vec![
+            Action {
+                name: "Bite".to_string(),
+                to_hit: Some(7),
+                damage: Some("2d10+4".to_string()),
+                damage_type: Some(DamageType::Piercing),
+                save_dc: None,
+                save_ability: None,
+                description: String::new(),
+            },
+            Action {
+                name: "Poison Breath".to_string(),
+                to_hit: None,
+                damage: Some("8d6".to_string()),
+                damage_type: Some(DamageType::Poison),
+                save_dc: Some(14),
+                save_ability: Some(Ability::Con),
+                description: String::new(),
+            },
+            Action {
+                name: "Frightful Presence".to_string(),
+                to_hit: None,
+                damage: None,
+                damage_type: None,
+                save_dc: None,
+                save_ability: None,
+                description: "Each creature of its choice within 120 feet".to_string(),
+            },
+        ];
+        dragon.limited_abilities = vec![LimitedAbility {
+            name: "Poison Breath".to_string(),
+            recharge: Some(5),
+            per_day: 0,
+            used: 0,
+        }];
+        let mut fighter = Entity::new("Fighter", EntityType::Player, 1, 18, 40);
+        fighter.initiative = Some(10);
+        let mut app = test_app(vec![dragon, fighter]);
+        let key = |c: char| KeyEvent::new(event::KeyCode::Char(c), event::KeyModifiers::NONE);
+
+        // a dragon doesn't bite itself
+        app.handle_key_event(key('1')).unwrap();
+        assert_eq!(app.popup.prompt, "Select the target of Bite first");
+        app.handle_popup_key_event(enter()).unwrap();
+
+        // nor breathes on itself
+        app.handle_key_event(key('2')).unwrap();
+        assert_eq!(app.popup.prompt, "Mark the targets of Poison Breath first");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.tracker.blocking_lock().entities[0].limited_abilities[0].is_available());
+
+        app.state.select(Some(1));
+        app.handle_key_event(key('1')).unwrap();
+        assert!(app.popup.prompt.starts_with("Dragon attacks Fighter"));
+        app.handle_popup_key_event(enter()).unwrap();
+
+        // the breath weapon opens the saving throw form, closing it doesn't use it up
+        app.handle_key_event(key('2')).unwrap();
+        assert!(app.form.active);
+        assert_eq!(app.form.values(), vec!["CON", "14", "8d6 poison"]);
+        app.form.hide();
+        assert!(app.tracker.blocking_lock().entities[0].limited_abilities[0].is_available());
+
+        // rolling it does and needs to recharge, one undo reverts the breath and its damage
+        let hp = app.tracker.blocking_lock().entities[1].current_hp;
+        app.handle_key_event(key('2')).unwrap();
+        app.handle_form_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.contains("CON save DC 14"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.tracker.blocking_lock().entities[0].limited_abilities[0].is_available());
+        assert!(app.tracker.blocking_lock().entities[1].current_hp < hp);
+        app.handle_key_event(key('2')).unwrap();
+        assert_eq!(app.popup.prompt, "Poison Breath of Dragon isn't available");
+        app.handle_popup_key_event(enter()).unwrap();
+        app.undo();
+        let tracker = app.tracker.blocking_lock();
+        assert!(tracker.entities[0].limited_abilities[0].is_available());
+        assert_eq!(tracker.entities[1].current_hp, hp);
+        drop(tracker);
+
+        app.handle_key_event(key('3')).unwrap();
+        assert!(app.popup.prompt.contains("Each creature of its choice"));
+        app.handle_popup_key_event(enter()).unwrap();
+        // numbers without an action do nothing
+        app.handle_key_event(key('4')).unwrap();
+        assert!(!app.popup.active);
+    }
+
+    #[test]
+    fn test_parse_group_save() {
+        let values = |values: [&str; 3]| values.map(String::from).to_vec();
+        let save = parse_group_save(&values(["wis", "13", ""])).unwrap();
+        assert_eq!(save.ability, Ability::Wis);
+        assert_eq!(save.dc, 13);
+        assert!(save.damage.is_none());
+        let save = parse_group_save(&values(["DEX", "15", "8d6 fire"])).unwrap();
+        assert_eq!(save.damage.unwrap().1, Some(DamageType::Fire));
+        assert_eq!(
+            parse_group_save(&values(["DEX", "hard", ""])).err(),
+            Some("DC needs to be a number".to_string())
+        );
+        assert!(parse_group_save(&values(["luck", "15", ""])).is_err());
+    }
+
+    #[test]
+    fn test_remove_entity_popup() {
+        let entities = [("Orc", 15), ("Goblin", 10)]
+            .into_iter()
+            .map(|(name, initiative)| {
+                let mut entity = Entity::new(name, EntityType::Monster, 0, 13, 15);
+                entity.initiative = Some(initiative);
+                entity
+            })
+            .collect();
+        let mut app = test_app(entities);
+        app.state.select(Some(1));
+
+        // declining keeps the entity
+        app.remove_entity();
+        assert!(app.popup.prompt.contains("Remove Goblin"));
+        app.handle_popup_key_event(KeyEvent::new(
+            event::KeyCode::Esc,
+            event::KeyModifiers::NONE,
+        ))
+        .unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities.len(), 2);
+
+        // `k` kills it and keeps it in the initiative
+        app.remove_entity();
+        app.popup.input.insert_str("k");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities.len(), 2);
+        assert!(app.tracker.blocking_lock().entities[1].is_dead());
+
+        app.remove_entity();
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities.len(), 1);
+        assert_eq!(app.state.selected(), Some(0));
+
+        // removing the one whose turn it is shows the start of the next turn
+        let mut troll = Entity::new("Troll", EntityType::Monster, 0, 15, 84);
+        troll.initiative = Some(5);
+        troll.current_hp = 50;
+        troll.triggers = vec!["start Regenerate heal 10".parse().unwrap()];
+        app.tracker.blocking_lock().add_entity(troll);
+        app.remove_entity();
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Troll, start of turn: Regenerate"));
+        assert_eq!(app.tracker.blocking_lock().entities[0].current_hp, 60);
+    }
+
+    #[test]
+    fn test_delay_and_ready() {
+        let entities = [("Merry", 20), ("Orc", 15), ("Goblin", 10)]
+            .into_iter()
+            .map(|(name, initiative)| {
+                let mut entity = Entity::new(name, EntityType::Monster, 0, 13, 15);
+                entity.initiative = Some(initiative);
+                entity
+            })
+            .collect();
+        let mut app = test_app(entities);
+
+        // Merry delays and acts after the Goblin
+        app.execute(Command::DelayTurn);
+        app.state.select(Some(0));
+        app.resume_turn();
+        assert!(app.popup.prompt.contains("3: Goblin"));
+        app.popup.input.insert_str("3");
+        app.handle_popup_key_event(enter()).unwrap();
+        let names = |app: &App| {
+            app.tracker
+                .blocking_lock()
+                .entities
+                .iter()
+                .map(|e| e.name.clone())
+                .join(", ")
+        };
+        assert_eq!(names(&app), "Orc, Goblin, Merry");
+        assert_eq!(app.state.selected(), Some(2));
+
+        app.ready_action();
+        app.popup.input.insert_str("Shove");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(
+            app.tracker.blocking_lock().entities[2].readied.as_deref(),
+            Some("Shove")
+        );
+        app.ready_action();
+        assert!(app.popup.prompt.contains("Merry readied Shove"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.tracker.blocking_lock().entities[2].readied.is_none());
+    }
+
+    #[test]
+    fn test_lair_and_legendary_actions() {
+        let mut dragon = Entity::new("Dragon", EntityType::Monster, 0, 19, 200);
+        dragon.initiative = Some(15);
+        dragon.legendary_actions = 3;
+        dragon.lair_actions = vec!["Magma erupts".to_string()];
+        let mut merry = Entity::new("Merry", EntityType::Player, 0, 16, 40);
+        merry.initiative = Some(21);
+        let mut app = test_app(vec![merry, dragon]);
+
+        app.next_turn();
+        assert!(app.popup.active);
+        assert!(app.popup.prompt.contains("Dragon: Magma erupts"));
+        app.handle_popup_key_event(enter()).unwrap();
+
+        app.state.select(Some(2));
+        app.use_legendary_action();
+        assert!(app.popup.prompt.contains("3/3 legendary actions"));
+        app.popup.input.insert_str("2");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(
+            app.tracker.blocking_lock().entities[2].legendary_actions_remaining(),
+            1
+        );
+
+        // entities without legendary actions don't ask
+        app.state.select(Some(0));
+        app.use_legendary_action();
+        assert!(!app.popup.active);
+    }
+
+    #[test]
+    fn test_turn_triggers() {
+        let mut troll = Entity::new("Troll", EntityType::Monster, 1, 15, 84);
+        troll.initiative = Some(10);
+        troll.current_hp = 50;
+        troll.triggers = vec!["start Regenerate heal 10".parse().unwrap()];
+        let mut merry = Entity::new("Merry", EntityType::Player, 0, 16, 40);
+        merry.initiative = Some(21);
+        merry.triggers = vec!["end Make a WIS save vs Hold Person".parse().unwrap()];
+        let mut app = test_app(vec![merry, troll]);
+
+        app.next_turn();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Merry, end of turn: Make a WIS save"));
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app
+            .popup
+            .prompt
+            .contains("Troll, start of turn: Regenerate"));
+        assert_eq!(app.tracker.blocking_lock().entities[1].current_hp, 60);
+        app.popup.input.insert_str("u");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.popup.active);
+        assert_eq!(app.tracker.blocking_lock().entities[1].current_hp, 50);
+
+        app.state.select(Some(1));
+        app.change_triggers();
+        assert!(app
+            .popup
+            .prompt
+            .contains("1: start of turn: Regenerate (heal 10)"));
+        app.popup
+            .input
+            .insert_str("end Searing Smite damage 1d6 fire");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert_eq!(app.tracker.blocking_lock().entities[1].triggers.len(), 2);
+
+        app.change_triggers();
+        app.popup.input.insert_str("sometimes Regenerate");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.popup.prompt.contains("expected 'start' or 'end'"));
+        app.handle_popup_key_event(enter()).unwrap();
+        app.popup.input.insert_str("1");
+        app.handle_popup_key_event(enter()).unwrap();
+        let tracker = app.tracker.blocking_lock();
+        assert_eq!(tracker.entities[1].triggers.len(), 1);
+        assert_eq!(tracker.entities[1].triggers[0].text, "Searing Smite");
+    }
+
+    #[test]
+    fn test_use_ability() {
+        let mut dragon = Entity::new("Dragon", EntityType::Monster, 0, 19, 200);
+        dragon.initiative = Some(15);
+        dragon.limited_abilities = vec![LimitedAbility {
+            name: "Fire Breath".to_string(),
+            recharge: Some(5),
+            per_day: 0,
+            used: 0,
+        }];
+        let mut app = test_app(vec![dragon]);
+
+        app.use_ability();
+        assert!(app.popup.prompt.contains("1: Fire Breath ● (Recharge 5-6)"));
+        app.popup.input.insert_str("1");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(!app.tracker.blocking_lock().entities[0].limited_abilities[0].is_available());
+
+        // entering it again restores it
+        app.use_ability();
+        assert!(app.popup.prompt.contains("1: Fire Breath ○"));
+        app.popup.input.insert_str("1");
+        app.handle_popup_key_event(enter()).unwrap();
+        assert!(app.tracker.blocking_lock().entities[0].limited_abilities[0].is_available());
+    }
 }
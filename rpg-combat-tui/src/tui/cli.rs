@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use clap::Parser;
+use core::combat::tracker::SkipPolicy;
 
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
@@ -20,4 +21,14 @@ pub struct Args {
     /// The path to the file that holds the information about the player characters
     #[arg(long, short)]
     pub player_characters: Option<PathBuf>,
+
+    /// Directory with monster stat blocks that the combat file references with `monster: <name>`.
+    /// Defaults to the `bestiary` directory next to the combat file.
+    #[arg(long, short)]
+    pub bestiary: Option<PathBuf>,
+
+    /// Which entities at 0 HP are skipped in the turn order: defeated, monsters (default) or none.
+    /// Overrides the policy stored in the combat file.
+    #[arg(long)]
+    pub skip: Option<SkipPolicy>,
 }
@@ -20,6 +20,7 @@ pub struct TableColors {
     selected_row_style_bg: Color,
     current_turn_style_fg: Color,
     current_turn_style_bg: Color,
+    defeated_row_fg: Color,
     normal_row_color: Color,
     alt_row_color: Color,
     footer_border_color: Color,
@@ -36,6 +37,7 @@ impl TableColors {
             selected_row_style_bg: material::GREEN.c100,
             current_turn_style_fg: material::BLACK,
             current_turn_style_bg: material::BLUE.c200,
+            defeated_row_fg: material::GRAY.c600,
             normal_row_color: material::GREEN.c900,
             alt_row_color: material::GREEN.c700,
             footer_border_color: material::GREEN.c400,
@@ -43,6 +45,54 @@ impl TableColors {
     }
 }
 
+fn draw_form(frame: &mut Frame, app: &App, area: Rect) -> Result<()> {
+    frame.render_widget(Clear, area);
+    let form_block = Block::default()
+        .title(Line::from(app.form.title.as_str()).centered())
+        .title_bottom(
+            Line::from(vec![
+                " Confirm ".into(),
+                "<Enter>".blue().bold(),
+                " Next Field ".into(),
+                "<Tab>".blue().bold(),
+                " Cancel ".into(),
+                "<Esc>".blue().bold(),
+            ])
+            .centered(),
+        )
+        .borders(Borders::ALL);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            app.form
+                .inputs
+                .iter()
+                .map(|_| Constraint::Length(1))
+                .chain([Constraint::Length(1), Constraint::Fill(1)]),
+        )
+        .margin(1)
+        .split(form_block.inner(area));
+    frame.render_widget(form_block, area);
+    for (i, (label, input)) in app.form.labels.iter().zip(&app.form.inputs).enumerate() {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(28), Constraint::Fill(1)])
+            .split(rows[i]);
+        let label = if i == app.form.focus {
+            Line::from(format!("{label}:").bold())
+        } else {
+            Line::from(format!("{label}:"))
+        };
+        frame.render_widget(label, columns[0]);
+        frame.render_widget(input, columns[1]);
+    }
+    if let Some(error) = &app.form.error {
+        let error = Paragraph::new(error.as_str().red()).wrap(Wrap { trim: false });
+        frame.render_widget(error, rows[app.form.inputs.len() + 1]);
+    }
+    Ok(())
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     // Cut the given rectangle into three vertical pieces
@@ -128,7 +178,10 @@ fn draw_table(frame: &mut Frame, app: &mut App, area: Rect) -> Result<()> {
         .height(1);
     let tracker = app.tracker.blocking_lock();
     let rows = tracker.entities.iter().enumerate().map(|(i, data)| {
-        let item = data.ref_array_string();
+        let mut item = data.ref_array_string();
+        if app.marked.contains(&data.uuid) {
+            item[1] = format!("✓ {}", item[1]);
+        }
         let color_bg = {
             if i == tracker.current_turn {
                 // highlight current turn
@@ -141,6 +194,8 @@ fn draw_table(frame: &mut Frame, app: &mut App, area: Rect) -> Result<()> {
             if i == tracker.current_turn {
                 // highlight current turn
                 app.colors.current_turn_style_fg
+            } else if data.is_dead() {
+                app.colors.defeated_row_fg
             } else {
                 ratatui::style::Color::Reset
             }
@@ -179,36 +234,183 @@ fn draw_table(frame: &mut Frame, app: &mut App, area: Rect) -> Result<()> {
     Ok(())
 }
 
-pub fn draw(frame: &mut Frame, app: &mut App) -> Result<()> {
-    let title = Line::from(
-        format!(
-            "RPG Combat TUI, Round: {}",
-            app.tracker.blocking_lock().round
+fn draw_log(frame: &mut Frame, app: &App, area: Rect) -> Result<()> {
+    let block = Block::default()
+        .title(Line::from(" Combat Log ").centered())
+        .borders(Borders::LEFT);
+    let tracker = app.tracker.blocking_lock();
+    let lines: Vec<Line> = tracker
+        .log
+        .iter()
+        .map(|entry| Line::from(entry.to_string()))
+        .collect();
+    // keep the latest entries in view unless the user scrolled up
+    let height = block.inner(area).height as usize;
+    let offset = lines.len().saturating_sub(height + app.log_scroll);
+    let log = Paragraph::new(lines)
+        .block(block)
+        .scroll((offset as u16, 0));
+    frame.render_widget(log, area);
+    Ok(())
+}
+
+fn draw_details(frame: &mut Frame, lines: Vec<String>, area: Rect) -> Result<()> {
+    let block = Block::default()
+        .title(Line::from(" Details ").centered())
+        .borders(Borders::TOP);
+    let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+    let details = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    frame.render_widget(details, area);
+    Ok(())
+}
+
+/// The numbered actions of the entity whose turn it is, rolled with the number keys
+fn draw_actions(frame: &mut Frame, title: String, lines: Vec<String>, area: Rect) -> Result<()> {
+    let block = Block::default()
+        .title(Line::from(title).centered())
+        .borders(Borders::TOP);
+    let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+    Ok(())
+}
+
+/// Draws the table with the details of the selected entity and the actions
+/// of the entity whose turn it is below it
+fn draw_combatants(frame: &mut Frame, app: &mut App, area: Rect) -> Result<()> {
+    let (details, actions) = {
+        let tracker = app.tracker.blocking_lock();
+        let details = app
+            .state
+            .selected()
+            .filter(|_| app.show_details)
+            .and_then(|selected| tracker.entities.get(selected).map(|e| e.details()));
+        let actions = tracker
+            .get_current_entity()
+            .filter(|e| !e.actions.is_empty())
+            .map(|e| {
+                let lines = e
+                    .actions
+                    .iter()
+                    .take(9)
+                    .enumerate()
+                    .map(|(i, action)| format!("{}: {action}", i + 1))
+                    .collect::<Vec<_>>();
+                (format!(" Actions of {} ", e.display_name()), lines)
+            });
+        (details, actions)
+    };
+    let mut constraints = vec![Constraint::Fill(1)];
+    if let Some(details) = &details {
+        constraints.push(Constraint::Length(details.len() as u16 + 1));
+    }
+    if let Some((_, lines)) = &actions {
+        constraints.push(Constraint::Length(lines.len() as u16 + 1));
+    }
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(area);
+    draw_table(frame, app, layout[0])?;
+    let mut next = 1;
+    if let Some(details) = details {
+        draw_details(frame, details, layout[next])?;
+        next += 1;
+    }
+    if let Some((title, lines)) = actions {
+        draw_actions(frame, title, lines, layout[next])?;
+    }
+    Ok(())
+}
+
+/// All key bindings shown in the help, the border only has room for the most used ones
+const KEY_BINDINGS: [(&str, &str); 34] = [
+    ("<Left>", "Prev Turn"),
+    ("<Right>", "Next Turn"),
+    ("<Up>", "Select Up"),
+    ("<Down>", "Select Down"),
+    ("C", "Toggle Condition"),
+    ("E", "Toggle Effect"),
+    ("<Shift>+C", "Concentration"),
+    ("D", "Assign Damage"),
+    ("H", "Heal"),
+    ("T", "Temp HP"),
+    ("<Ctrl>+R", "Re-roll Initiative"),
+    ("u", "Undo"),
+    ("<Shift>+U", "Redo"),
+    ("<Esc>", "Quit"),
+    ("<Ctrl>+C", "Reset Combat"),
+    ("A", "Add Combatants"),
+    ("X", "Remove"),
+    ("W", "Delay"),
+    ("<Shift>+W", "Resume"),
+    ("<Shift>+R", "Ready"),
+    ("<Shift>+L", "Legendary Action"),
+    ("<Shift>+T", "Triggers"),
+    ("<Shift>+A", "Abilities"),
+    ("M", "Mark"),
+    ("G", "Mark Group"),
+    ("<Ctrl>+A", "Mark Monsters"),
+    ("<Shift>+M", "Clear Marks"),
+    ("S", "Saving Throw"),
+    ("K", "Attack"),
+    ("1-9", "Roll Action"),
+    ("L", "Toggle Log"),
+    ("V", "Toggle Details"),
+    ("<PgUp>/<PgDn>", "Scroll Log"),
+    ("?", "Help"),
+];
+
+/// Lists all key bindings in two columns over the combatants
+fn draw_help(frame: &mut Frame, area: Rect) -> Result<()> {
+    frame.render_widget(Clear, area);
+    let block = Block::default()
+        .title(Line::from(" Key Bindings ").centered())
+        .title_bottom(Line::from(vec![" Close ".into(), "<Any Key> ".blue().bold()]).centered())
+        .borders(Borders::ALL);
+    let rows = KEY_BINDINGS.chunks(2).map(|bindings| {
+        Row::new(
+            bindings
+                .iter()
+                .flat_map(|(key, action)| [Cell::from(key.blue().bold()), Cell::from(*action)]),
         )
-        .bold(),
+    });
+    let widths = [
+        Constraint::Length(13),
+        Constraint::Fill(1),
+        Constraint::Length(13),
+        Constraint::Fill(1),
+    ];
+    frame.render_widget(Table::new(rows, widths).block(block), area);
+    Ok(())
+}
+
+pub fn draw(frame: &mut Frame, app: &mut App) -> Result<()> {
+    let mut title = format!(
+        "RPG Combat TUI, Round: {}",
+        app.tracker.blocking_lock().round
     );
+    if !app.marked.is_empty() {
+        title += &format!(", Marked: {}", app.marked.len());
+    }
+    let title = Line::from(title.bold());
 
     let instructions = Line::from(vec![
         " Prev Turn ".into(),
         "<Left>".blue().bold(),
         " Next Turn ".into(),
         "<Right>".blue().bold(),
-        " Select Up ".into(),
-        "<Up>".blue().bold(),
-        " Select Down ".into(),
-        "<Down>".blue().bold(),
-        " Toggle Condition ".into(),
-        "C".blue().bold(),
         " Assign Damage ".into(),
         "D".blue().bold(),
         " Heal ".into(),
         "H".blue().bold(),
-        " Re-roll Initiative ".into(),
-        "<Ctrl>+R".blue().bold(),
+        " Undo ".into(),
+        "u".blue().bold(),
         " Quit ".into(),
-        "<Esc> ".blue().bold(),
-        " Reset Combat ".into(),
-        "<Ctrl>+C".blue().bold(),
+        "<Esc>".blue().bold(),
+        " Help ".into(),
+        "? ".blue().bold(),
     ]);
     let block = Block::default()
         .title(title.centered())
@@ -217,7 +419,24 @@ pub fn draw(frame: &mut Frame, app: &mut App) -> Result<()> {
         .border_set(border::THICK);
     frame.render_widget(&block, frame.area());
     let inner_area = block.inner(frame.area());
-    draw_table(frame, app, inner_area)?;
+    if app.show_log {
+        let layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .split(inner_area);
+        draw_combatants(frame, app, layout[0])?;
+        draw_log(frame, app, layout[1])?;
+    } else {
+        draw_combatants(frame, app, inner_area)?;
+    }
+    if app.show_help {
+        let help_area = centered_rect(80, 80, frame.area());
+        draw_help(frame, help_area)?;
+    }
+    if app.form.active {
+        let form_area = centered_rect(40, 40, frame.area());
+        draw_form(frame, app, form_area)?;
+    }
     if app.popup.active {
         let popup_area = centered_rect(app.popup.size.0, app.popup.size.1, frame.area());
         draw_popup(frame, app, popup_area)?;
@@ -5,6 +5,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use core::combat::bestiary::Bestiary;
+
 use super::cli::Args;
 
 /// Returns the path to the save state for the given combat file if it exists
@@ -52,6 +54,23 @@ pub fn load_combat_yaml(args: &Args) -> Result<String> {
     }
 }
 
+/// Loads the bestiary given on the command line or the `bestiary` directory next to the combat file.
+/// Without either the bestiary is empty.
+pub fn load_bestiary(args: &Args) -> Result<Bestiary> {
+    let dir = match &args.bestiary {
+        Some(dir) => dir.clone(),
+        None => {
+            let dir = args.combat_file.with_file_name("bestiary");
+            if !dir.is_dir() {
+                return Ok(Bestiary::new());
+            }
+            dir
+        }
+    };
+    info!("Reading bestiary from {}", &dir.display());
+    Ok(Bestiary::load_dir(&dir)?)
+}
+
 pub fn read_file_with_context(path: &Path) -> Result<String> {
     fs::read_to_string(path).wrap_err_with(|| format!("Failed to read file '{}'.", path.display()))
 }